@@ -0,0 +1,144 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process::ExitCode,
+};
+
+use advent_of_code::intcode::{self, Intcode, State};
+
+const USAGE: &str = "Usage: aoc run --intcode [FILE]
+
+Runs an Intcode program from FILE, or stdin if FILE is not given, and prints
+its output as ASCII.";
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<String>>();
+
+    match run(&args, &mut io::stdout()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String], out: &mut impl Write) -> Result<(), String> {
+    let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    let source = match args.as_slice() {
+        ["run", "--intcode"] => {
+            let mut source = String::new();
+            io::stdin()
+                .read_to_string(&mut source)
+                .map_err(|e| format!("Could not read stdin: {}", e))?;
+            source
+        }
+        ["run", "--intcode", path] => {
+            fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?
+        }
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let program = intcode::parse(&source).map_err(|e| format!("Could not parse program: {}", e))?;
+    let mut computer = Intcode::new(program);
+    let result = computer.run();
+
+    // Print whatever was output before the program stopped, even on an error.
+    write!(out, "{}", intcode::to_ascii(&computer.take_output()))
+        .map_err(|e| format!("Could not write output: {}", e))?;
+
+    match result.map_err(|e| format!("Program failed: {}", e))? {
+        State::Halted => Ok(()),
+        State::WaitingForInput => Err("Program is waiting for input".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::Path, process};
+
+    use super::run;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn day_07_input() {
+        let input_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src")
+            .join("day_07")
+            .join("input");
+        let mut out = Vec::new();
+
+        run(
+            &args(&["run", "--intcode", input_path.to_str().unwrap()]),
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Ceci n'est pas une intcode program\n"
+        );
+    }
+
+    /// Runs `program` from a temporary file, returning the result and output.
+    fn run_program(name: &str, program: &str) -> (Result<(), String>, String) {
+        let path = env::temp_dir().join(format!("aoc-{}-{}", name, process::id()));
+        fs::write(&path, program).unwrap();
+        let mut out = Vec::new();
+
+        let result = run(
+            &args(&["run", "--intcode", path.to_str().unwrap()]),
+            &mut out,
+        );
+        fs::remove_file(&path).unwrap();
+
+        (result, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn invalid_program_test() {
+        assert_eq!(
+            run_program("invalid", "1,2,x"),
+            (
+                Err("Could not parse program: Invalid value \"x\" at position 2".to_string()),
+                String::new()
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_opcode_test() {
+        assert_eq!(
+            run_program("opcode", "42"),
+            (
+                Err("Program failed: Unknown opcode 42 at 0".to_string()),
+                String::new()
+            )
+        );
+    }
+
+    #[test]
+    fn negative_address_test() {
+        // Prints "H" before failing.
+        assert_eq!(
+            run_program("negative", "104,72,4,-5,99"),
+            (
+                Err("Program failed: Negative address -5 at 2".to_string()),
+                "H".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn usage_test() {
+        let mut out = Vec::new();
+
+        assert!(run(&args(&[]), &mut out).is_err());
+        assert!(run(&args(&["run"]), &mut out).is_err());
+        assert!(run(&args(&["run", "--intcode", "a", "b"]), &mut out).is_err());
+    }
+}
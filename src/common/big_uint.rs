@@ -0,0 +1,157 @@
+use std::{
+    fmt::{self, Display},
+    ops::{Add, Mul},
+};
+
+/// An arbitrary precision unsigned integer.
+///
+/// Only supports what the puzzles need: addition, multiplication and printing.
+/// Digits are stored as little-endian base 2^32 limbs with no trailing zeros,
+/// so zero is the empty vec and equality can be derived.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BigUint(Vec<u32>);
+
+impl BigUint {
+    pub fn zero() -> Self {
+        Self(Vec::new())
+    }
+
+    fn trim(mut self) -> Self {
+        while self.0.last() == Some(&0) {
+            self.0.pop();
+        }
+        self
+    }
+
+    /// Divides in place by a small divisor and returns the remainder.
+    fn div_rem_small(&mut self, divisor: u32) -> u32 {
+        let mut remainder = 0u64;
+        for limb in self.0.iter_mut().rev() {
+            let current = (remainder << 32) | *limb as u64;
+            *limb = (current / divisor as u64) as u32;
+            remainder = current % divisor as u64;
+        }
+        *self = std::mem::take(self).trim();
+        remainder as u32
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(x: u64) -> Self {
+        Self(vec![x as u32, (x >> 32) as u32]).trim()
+    }
+}
+
+impl Add for BigUint {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let (mut longer, shorter) = if self.0.len() >= other.0.len() {
+            (self, other)
+        } else {
+            (other, self)
+        };
+
+        let mut carry = 0u64;
+        for (i, limb) in longer.0.iter_mut().enumerate() {
+            if i >= shorter.0.len() && carry == 0 {
+                break;
+            }
+            let sum = *limb as u64 + *shorter.0.get(i).unwrap_or(&0) as u64 + carry;
+            *limb = sum as u32;
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            longer.0.push(carry as u32);
+        }
+
+        longer
+    }
+}
+
+impl Mul for BigUint {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        if self.0.is_empty() || other.0.is_empty() {
+            return Self::zero();
+        }
+
+        let mut result = vec![0u32; self.0.len() + other.0.len()];
+
+        for (i, a) in self.0.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, b) in other.0.iter().enumerate() {
+                let current = result[i + j] as u64 + *a as u64 * *b as u64 + carry;
+                result[i + j] = current as u32;
+                carry = current >> 32;
+            }
+            result[i + other.0.len()] = carry as u32;
+        }
+
+        Self(result).trim()
+    }
+}
+
+impl Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const CHUNK: u32 = 1_000_000_000;
+
+        let mut remaining = self.clone();
+        let mut chunks = Vec::new();
+        while !remaining.0.is_empty() {
+            chunks.push(remaining.div_rem_small(CHUNK));
+        }
+
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((most_significant, rest)) => {
+                write!(f, "{}", most_significant)?;
+                rest.iter()
+                    .rev()
+                    .try_for_each(|chunk| write!(f, "{:09}", chunk))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigUint;
+
+    #[test]
+    fn display_test() {
+        assert_eq!(BigUint::zero().to_string(), "0");
+        assert_eq!(BigUint::from(0).to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_007).to_string(), "1000000007");
+        assert_eq!(BigUint::from(u64::MAX).to_string(), u64::MAX.to_string());
+    }
+
+    #[test]
+    fn add_test() {
+        assert_eq!(
+            BigUint::from(u32::MAX as u64) + BigUint::from(1),
+            BigUint::from(1 << 32)
+        );
+        assert_eq!(
+            (BigUint::from(u64::MAX) + BigUint::from(u64::MAX)).to_string(),
+            (u64::MAX as u128 * 2).to_string()
+        );
+    }
+
+    #[test]
+    fn mul_test() {
+        assert_eq!(BigUint::from(12) * BigUint::zero(), BigUint::zero());
+        assert_eq!(
+            (BigUint::from(u64::MAX) * BigUint::from(u64::MAX)).to_string(),
+            (u64::MAX as u128 * u64::MAX as u128).to_string()
+        );
+
+        // 2^200
+        let power = (0..200).fold(BigUint::from(1), |acc, _| acc * BigUint::from(2));
+        assert_eq!(
+            power.to_string(),
+            "1606938044258990275541962092341162602522202993782792835301376"
+        );
+    }
+}
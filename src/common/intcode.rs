@@ -0,0 +1,352 @@
+use std::collections::VecDeque;
+
+/// An Intcode computer (Advent of Code 2019).
+///
+/// Supports all ten opcodes, position, immediate and relative parameter modes,
+/// and memory that grows on demand. Inputs and outputs are queued so the
+/// program can be run in steps, pausing whenever it needs more input.
+#[derive(Debug, Clone)]
+pub struct Intcode {
+    memory: Vec<i64>,
+    instruction_pointer: usize,
+    relative_base: i64,
+    input: VecDeque<i64>,
+    output: VecDeque<i64>,
+}
+
+/// The highest address a program may write to, so a stray write can't grow
+/// memory without limit.
+const MAX_ADDRESS: usize = 1 << 20;
+
+/// Why [`Intcode::run`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Halted,
+    /// The program needs more input. Push some and run again to resume.
+    WaitingForInput,
+}
+
+/// Parses a comma separated program.
+pub fn parse(source: &str) -> Result<Vec<i64>, String> {
+    source
+        .trim()
+        .split(',')
+        .enumerate()
+        .map(|(i, x)| {
+            x.trim()
+                .parse()
+                .map_err(|_| format!("Invalid value {:?} at position {}", x.trim(), i))
+        })
+        .collect()
+}
+
+/// Renders output as text. Values that aren't ASCII are written in decimal on
+/// their own line.
+pub fn to_ascii(output: &[i64]) -> String {
+    output
+        .iter()
+        .map(|value| match u8::try_from(*value) {
+            Ok(byte) if byte.is_ascii() => (byte as char).to_string(),
+            _ => format!("{}\n", value),
+        })
+        .collect()
+}
+
+impl Intcode {
+    pub fn new(program: Vec<i64>) -> Self {
+        Self {
+            memory: program,
+            instruction_pointer: 0,
+            relative_base: 0,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+        }
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.input.push_back(value);
+    }
+
+    /// Removes and returns everything output so far.
+    pub fn take_output(&mut self) -> Vec<i64> {
+        self.output.drain(..).collect()
+    }
+
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+
+    /// Runs until the program halts or needs input that hasn't been pushed.
+    ///
+    /// Returns an error for an unknown opcode or parameter mode, an address
+    /// that is negative or beyond [`MAX_ADDRESS`], or arithmetic that
+    /// overflows.
+    pub fn run(&mut self) -> Result<State, String> {
+        loop {
+            let instruction = self.read(self.instruction_pointer);
+
+            match instruction % 100 {
+                // Add.
+                1 => {
+                    let result = self
+                        .parameter(instruction, 1)?
+                        .checked_add(self.parameter(instruction, 2)?)
+                        .ok_or_else(|| self.overflow())?;
+                    self.write(instruction, 3, result)?;
+                    self.instruction_pointer += 4;
+                }
+                // Multiply.
+                2 => {
+                    let result = self
+                        .parameter(instruction, 1)?
+                        .checked_mul(self.parameter(instruction, 2)?)
+                        .ok_or_else(|| self.overflow())?;
+                    self.write(instruction, 3, result)?;
+                    self.instruction_pointer += 4;
+                }
+                // Input.
+                3 => {
+                    let Some(value) = self.input.pop_front() else {
+                        return Ok(State::WaitingForInput);
+                    };
+                    self.write(instruction, 1, value)?;
+                    self.instruction_pointer += 2;
+                }
+                // Output.
+                4 => {
+                    let value = self.parameter(instruction, 1)?;
+                    self.output.push_back(value);
+                    self.instruction_pointer += 2;
+                }
+                // Jump if true.
+                5 => {
+                    if self.parameter(instruction, 1)? != 0 {
+                        self.instruction_pointer = self.address(self.parameter(instruction, 2)?)?;
+                    } else {
+                        self.instruction_pointer += 3;
+                    }
+                }
+                // Jump if false.
+                6 => {
+                    if self.parameter(instruction, 1)? == 0 {
+                        self.instruction_pointer = self.address(self.parameter(instruction, 2)?)?;
+                    } else {
+                        self.instruction_pointer += 3;
+                    }
+                }
+                // Less than.
+                7 => {
+                    let result =
+                        self.parameter(instruction, 1)? < self.parameter(instruction, 2)?;
+                    self.write(instruction, 3, result as i64)?;
+                    self.instruction_pointer += 4;
+                }
+                // Equals.
+                8 => {
+                    let result =
+                        self.parameter(instruction, 1)? == self.parameter(instruction, 2)?;
+                    self.write(instruction, 3, result as i64)?;
+                    self.instruction_pointer += 4;
+                }
+                // Adjust relative base.
+                9 => {
+                    self.relative_base = self
+                        .relative_base
+                        .checked_add(self.parameter(instruction, 1)?)
+                        .ok_or_else(|| self.overflow())?;
+                    self.instruction_pointer += 2;
+                }
+                99 => return Ok(State::Halted),
+                opcode => {
+                    return Err(format!(
+                        "Unknown opcode {} at {}",
+                        opcode, self.instruction_pointer
+                    ))
+                }
+            }
+        }
+    }
+
+    fn mode(instruction: i64, offset: usize) -> i64 {
+        instruction / 10_i64.pow(offset as u32 + 1) % 10
+    }
+
+    /// Address of the `offset`th parameter of the current instruction.
+    fn parameter_address(&self, instruction: i64, offset: usize) -> Result<usize, String> {
+        let raw = self.read(self.instruction_pointer + offset);
+
+        match Self::mode(instruction, offset) {
+            0 => self.address(raw),
+            2 => self.address(
+                self.relative_base
+                    .checked_add(raw)
+                    .ok_or_else(|| self.overflow())?,
+            ),
+            mode => Err(format!(
+                "Parameter mode {} has no address at {}",
+                mode, self.instruction_pointer
+            )),
+        }
+    }
+
+    fn parameter(&self, instruction: i64, offset: usize) -> Result<i64, String> {
+        match Self::mode(instruction, offset) {
+            1 => Ok(self.read(self.instruction_pointer + offset)),
+            _ => Ok(self.read(self.parameter_address(instruction, offset)?)),
+        }
+    }
+
+    fn write(&mut self, instruction: i64, offset: usize, value: i64) -> Result<(), String> {
+        let address = self.parameter_address(instruction, offset)?;
+        if address > MAX_ADDRESS {
+            return Err(format!(
+                "Address {} is beyond the memory limit at {}",
+                address, self.instruction_pointer
+            ));
+        }
+
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, 0);
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    fn read(&self, address: usize) -> i64 {
+        // Memory beyond the program starts out as 0.
+        *self.memory.get(address).unwrap_or(&0)
+    }
+
+    fn address(&self, value: i64) -> Result<usize, String> {
+        usize::try_from(value)
+            .map_err(|_| format!("Negative address {} at {}", value, self.instruction_pointer))
+    }
+
+    fn overflow(&self) -> String {
+        format!("Overflow at {}", self.instruction_pointer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::read_input_to_string;
+
+    use super::{parse, to_ascii, Intcode, State};
+
+    fn run_with_input(program: &str, input: &[i64]) -> Vec<i64> {
+        let mut computer = Intcode::new(parse(program).unwrap());
+        input.iter().for_each(|value| computer.push_input(*value));
+
+        assert_eq!(computer.run(), Ok(State::Halted));
+        computer.take_output()
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let mut computer = Intcode::new(parse("1,9,10,3,2,3,11,0,99,30,40,50").unwrap());
+        assert_eq!(computer.run(), Ok(State::Halted));
+        assert_eq!(computer.memory()[0], 3500);
+
+        // Immediate mode and negative values.
+        let mut computer = Intcode::new(parse("1101,100,-1,4,0").unwrap());
+        assert_eq!(computer.run(), Ok(State::Halted));
+        assert_eq!(computer.memory()[4], 99);
+    }
+
+    #[test]
+    fn comparison_and_jump_test() {
+        // Outputs 999 if the input is below 8, 1000 if it is 8, 1001 if above.
+        let program = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,\
+            1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,\
+            999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+
+        assert_eq!(run_with_input(program, &[7]), vec![999]);
+        assert_eq!(run_with_input(program, &[8]), vec![1000]);
+        assert_eq!(run_with_input(program, &[9]), vec![1001]);
+
+        // Position mode equals.
+        assert_eq!(run_with_input("3,9,8,9,10,9,4,9,99,-1,8", &[8]), vec![1]);
+        assert_eq!(run_with_input("3,9,8,9,10,9,4,9,99,-1,8", &[5]), vec![0]);
+    }
+
+    #[test]
+    fn relative_mode_test() {
+        // Outputs a copy of itself.
+        let quine = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+        assert_eq!(run_with_input(quine, &[]), parse(quine).unwrap());
+
+        assert_eq!(
+            run_with_input("1102,34915192,34915192,7,4,7,99,0", &[]),
+            vec![1219070632396864]
+        );
+        assert_eq!(
+            run_with_input("104,1125899906842624,99", &[]),
+            vec![1125899906842624]
+        );
+    }
+
+    #[test]
+    fn waiting_for_input_test() {
+        // Echoes two inputs.
+        let mut computer = Intcode::new(parse("3,11,4,11,3,11,4,11,99").unwrap());
+
+        assert_eq!(computer.run(), Ok(State::WaitingForInput));
+        computer.push_input(4);
+        assert_eq!(computer.run(), Ok(State::WaitingForInput));
+        assert_eq!(computer.take_output(), vec![4]);
+
+        computer.push_input(2);
+        assert_eq!(computer.run(), Ok(State::Halted));
+        assert_eq!(computer.take_output(), vec![2]);
+    }
+
+    #[test]
+    fn error_test() {
+        let run = |program: &str| Intcode::new(parse(program).unwrap()).run();
+
+        assert_eq!(run("42"), Err("Unknown opcode 42 at 0".to_string()));
+        assert_eq!(run("4,-5,99"), Err("Negative address -5 at 0".to_string()));
+        assert_eq!(
+            run("1101,0,0,1000000000,99"),
+            Err("Address 1000000000 is beyond the memory limit at 0".to_string())
+        );
+        assert_eq!(
+            run("11101,1,1,0,99"),
+            Err("Parameter mode 1 has no address at 0".to_string())
+        );
+        assert_eq!(
+            run("104,5,304,0,99"),
+            Err("Parameter mode 3 has no address at 2".to_string())
+        );
+        assert_eq!(
+            run("1102,9223372036854775807,2,0,99"),
+            Err("Overflow at 0".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_test() {
+        assert_eq!(parse(" 1, -2,3\n"), Ok(vec![1, -2, 3]));
+        assert_eq!(
+            parse("1,2,x"),
+            Err("Invalid value \"x\" at position 2".to_string())
+        );
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn to_ascii_test() {
+        assert_eq!(to_ascii(&[72, 105, 10]), "Hi\n");
+        assert_eq!(to_ascii(&[72, 1000, -1]), "H1000\n-1\n");
+    }
+
+    #[test]
+    fn day_07_input() {
+        // The crab positions are an Intcode program.
+        let input = read_input_to_string("day_07");
+        assert_eq!(parse(&input).unwrap().len(), 1000);
+
+        let output = run_with_input(&input, &[]);
+        assert_eq!(to_ascii(&output), "Ceci n'est pas une intcode program\n");
+    }
+}
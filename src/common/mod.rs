@@ -1,12 +1,18 @@
+#[cfg(test)]
 use std::{
     fs::{read_to_string, File},
     io::{BufRead, BufReader},
     path::Path,
 };
 
+pub mod big_uint;
+pub mod intcode;
+pub mod rng;
+
 /// Reads the puzzle input.
 ///
 /// The file is expected to be placed in `$crate_root/src/$day/input`.
+#[cfg(test)]
 pub fn read_input(day: &str) -> impl Iterator<Item = String> {
     let input_path = Path::new(env!("CARGO_MANIFEST_DIR"))
         .join("src")
@@ -21,6 +27,7 @@ pub fn read_input(day: &str) -> impl Iterator<Item = String> {
 /// Reads the puzzle input to a string.
 ///
 /// The file is expected to be placed in `$crate_root/src/$day/input`.
+#[cfg(test)]
 pub fn read_input_to_string(day: &str) -> String {
     let input_path = Path::new(env!("CARGO_MANIFEST_DIR"))
         .join("src")
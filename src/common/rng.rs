@@ -0,0 +1,108 @@
+/// A small seedable pseudo-random number generator (SplitMix64).
+///
+/// This is not suitable for anything security related. It exists so that
+/// simulations are reproducible from a seed without pulling in a crate.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Creates a generator for the `stream`th independent sequence of `seed`.
+    ///
+    /// The same `(seed, stream)` always gives the same sequence, so work can be
+    /// split across threads without the result depending on how it was split.
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut mixer = Self::new(seed ^ stream.wrapping_mul(GOLDEN_GAMMA));
+        Self::new(mixer.next_u64())
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a uniformly distributed number in `0..bound`.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "Bound must be positive");
+
+        // Reject the top partial range so that every residue is equally likely.
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % bound;
+            }
+        }
+    }
+
+    /// Shuffles `items` in place (Fisher-Yates).
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i as u64 + 1) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn streams_differ() {
+        let a = (0..10)
+            .scan(Rng::with_stream(42, 0), |rng, _| Some(rng.next_u64()))
+            .collect::<Vec<_>>();
+        let b = (0..10)
+            .scan(Rng::with_stream(42, 1), |rng, _| Some(rng.next_u64()))
+            .collect::<Vec<_>>();
+
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn below_test() {
+        let mut rng = Rng::new(7);
+        let mut seen = [false; 6];
+
+        for _ in 0..1000 {
+            let x = rng.below(6) as usize;
+            seen[x] = true;
+        }
+
+        assert!(seen.iter().all(|x| *x));
+    }
+
+    #[test]
+    fn shuffle_is_permutation() {
+        let mut rng = Rng::new(1);
+        let mut items = (0..50).collect::<Vec<u32>>();
+        rng.shuffle(&mut items);
+
+        assert_ne!(items, (0..50).collect::<Vec<u32>>());
+
+        items.sort();
+        assert_eq!(items, (0..50).collect::<Vec<u32>>());
+    }
+}
@@ -1,4 +1,6 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, thread};
+
+use crate::common::rng::Rng;
 
 /// Assumptions:
 ///
@@ -73,6 +75,35 @@ impl BingoBoard {
             .sum();
         (board_sum - board_sum_marked) * last_draw as u64
     }
+
+    /// Returns the turn the board wins on, where `turns[number]` is the turn
+    /// `number` is drawn on, or `None` if the board never wins.
+    fn winning_turn(&self, turns: &[Option<usize>; 256]) -> Option<usize> {
+        self.lines()
+            .into_iter()
+            .filter_map(|line| {
+                line.into_iter()
+                    .map(|val| turns[val as usize])
+                    .collect::<Option<Vec<usize>>>()
+                    .and_then(|line_turns| line_turns.into_iter().max())
+            })
+            .min()
+    }
+
+    /// Returns the rows followed by the columns of the board.
+    fn lines(&self) -> Vec<[u8; 5]> {
+        let rows = self.0.iter().copied();
+        let columns = (0..5).map(|i| self.0.map(|row| row[i]));
+
+        rows.chain(columns).collect()
+    }
+
+    /// Whether every number of one of the board's lines is in `draws`.
+    fn has_line_within(&self, draws: &HashSet<u8>) -> bool {
+        self.lines()
+            .iter()
+            .any(|line| line.iter().all(|val| draws.contains(val)))
+    }
 }
 
 struct BingoBoardIter<'a> {
@@ -103,7 +134,7 @@ fn bingo_first_to_win_score(draws: Vec<u8>, matrices: Vec<[[u8; 5]; 5]>) -> u64
         .map(|matrix| BingoBoard::new(matrix))
         .collect::<Vec<BingoBoard>>();
 
-    for i in 1..draws.len() {
+    for i in 1..=draws.len() {
         let current_draws = &draws[0..i];
 
         let maybe_winning_score = bingo_boards.iter().find_map(|board| {
@@ -136,7 +167,7 @@ fn bingo_last_to_win_score(draws: Vec<u8>, matrices: Vec<[[u8; 5]; 5]>) -> u64 {
     let mut alive_board_indices: HashSet<usize> =
         HashSet::from_iter((0..bingo_boards.len()).into_iter());
 
-    for i in 1..draws.len() {
+    for i in 1..=draws.len() {
         let current_draws = &draws[0..i];
 
         if alive_board_indices.len() == 1 {
@@ -170,6 +201,157 @@ fn bingo_last_to_win_score(draws: Vec<u8>, matrices: Vec<[[u8; 5]; 5]>) -> u64 {
     panic!("No winning boards found after all draws")
 }
 
+/// Builds a shortest draw order that makes the board at `target` win strictly
+/// before every other board.
+///
+/// A board can only win once all 5 numbers of one of its lines are drawn, so
+/// no order is shorter than 5. Marks only ever accumulate, so drawing one of
+/// the target's lines works exactly when no other board has a line made up of
+/// numbers from it; drawing anything extra can only help the other boards.
+///
+/// If no order exists, returns for each of the target's lines (rows followed
+/// by columns) the index of a board that wins no later than the target.
+#[allow(dead_code)]
+fn draws_for_target_to_win(
+    matrices: Vec<[[u8; 5]; 5]>,
+    target: usize,
+) -> Result<Vec<u8>, Vec<usize>> {
+    let bingo_boards = matrices
+        .into_iter()
+        .map(BingoBoard::new)
+        .collect::<Vec<BingoBoard>>();
+
+    let target_board = bingo_boards.get(target).expect("Target board should exist");
+
+    let mut blockers = Vec::new();
+
+    for line in target_board.lines() {
+        let draws: HashSet<u8> = HashSet::from_iter(line);
+
+        let blocker = bingo_boards
+            .iter()
+            .enumerate()
+            .find(|(i, board)| *i != target && board.has_line_within(&draws));
+
+        match blocker {
+            Some((i, _)) => blockers.push(i),
+            None => return Ok(line.to_vec()),
+        }
+    }
+
+    Err(blockers)
+}
+
+/// Chance of a board winning first and last over random draw orders.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct WinProbability {
+    first: f64,
+    last: f64,
+}
+
+/// Estimates the [`WinProbability`] of each board by shuffling `draws` for
+/// `trials` rounds, split over `threads` threads.
+///
+/// Each trial is seeded from `(seed, trial)`, so the estimate only depends on
+/// `seed` and not on how many threads are used. When several boards tie for
+/// first or last, the trial is split equally between them. Boards that never
+/// win in a trial are ignored for that trial.
+#[allow(dead_code)]
+fn win_probabilities(
+    draws: &[u8],
+    matrices: Vec<[[u8; 5]; 5]>,
+    trials: usize,
+    seed: u64,
+    threads: usize,
+) -> Vec<WinProbability> {
+    assert!(trials > 0, "There should be at least one trial");
+    assert!(threads > 0, "There should be at least one thread");
+
+    let bingo_boards = matrices
+        .into_iter()
+        .map(BingoBoard::new)
+        .collect::<Vec<BingoBoard>>();
+
+    let chunk_size = trials.div_ceil(threads).max(1);
+
+    let tallies = thread::scope(|scope| {
+        let handles = (0..trials)
+            .step_by(chunk_size)
+            .map(|start| {
+                let end = (start + chunk_size).min(trials);
+                let bingo_boards = &bingo_boards;
+                scope.spawn(move || tally_wins(bingo_boards, draws, start..end, seed))
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+
+    let mut result = vec![WinProbability::default(); bingo_boards.len()];
+    for tally in tallies {
+        for (total, partial) in result.iter_mut().zip(tally) {
+            total.first += partial.first;
+            total.last += partial.last;
+        }
+    }
+
+    for probability in result.iter_mut() {
+        probability.first /= trials as f64;
+        probability.last /= trials as f64;
+    }
+
+    result
+}
+
+/// Counts the (possibly fractional) first and last wins of each board for the
+/// given range of trials.
+fn tally_wins(
+    bingo_boards: &[BingoBoard],
+    draws: &[u8],
+    trials: std::ops::Range<usize>,
+    seed: u64,
+) -> Vec<WinProbability> {
+    let mut result = vec![WinProbability::default(); bingo_boards.len()];
+    let mut shuffled = draws.to_vec();
+
+    for trial in trials {
+        shuffled.copy_from_slice(draws);
+        Rng::with_stream(seed, trial as u64).shuffle(&mut shuffled);
+
+        let mut turns = [None; 256];
+        for (turn, draw) in shuffled.iter().enumerate() {
+            turns[*draw as usize] = Some(turn);
+        }
+
+        let winning_turns = bingo_boards
+            .iter()
+            .map(|board| board.winning_turn(&turns))
+            .collect::<Vec<Option<usize>>>();
+
+        let first = winning_turns.iter().flatten().min();
+        let last = winning_turns.iter().flatten().max();
+
+        if let (Some(first), Some(last)) = (first, last) {
+            let first_count = winning_turns.iter().filter(|t| **t == Some(*first)).count();
+            let last_count = winning_turns.iter().filter(|t| **t == Some(*last)).count();
+
+            for (probability, turn) in result.iter_mut().zip(winning_turns.iter()) {
+                if *turn == Some(*first) {
+                    probability.first += 1.0 / first_count as f64;
+                }
+                if *turn == Some(*last) {
+                    probability.last += 1.0 / last_count as f64;
+                }
+            }
+        }
+    }
+
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use crate::common::read_input;
@@ -262,4 +444,177 @@ mod tests {
             assert_eq!(bingo_last_to_win_score(draws, matrices), 30070);
         }
     }
+
+    mod win_probabilities {
+        use crate::day_04::{
+            tests::{parse_input, parse_input_string, EXAMPLE_INPUT},
+            win_probabilities,
+        };
+
+        #[test]
+        fn sums_to_one() {
+            let (draws, matrices) = parse_input_string(EXAMPLE_INPUT);
+
+            let result = win_probabilities(&draws, matrices, 1000, 1, 4);
+
+            let first = result.iter().map(|p| p.first).sum::<f64>();
+            let last = result.iter().map(|p| p.last).sum::<f64>();
+            assert!((first - 1.0).abs() < 1e-9, "{}", first);
+            assert!((last - 1.0).abs() < 1e-9, "{}", last);
+        }
+
+        #[test]
+        fn reproducible_across_thread_counts() {
+            let (draws, matrices) = parse_input_string(EXAMPLE_INPUT);
+
+            let single = win_probabilities(&draws, matrices.clone(), 500, 7, 1);
+            let multi = win_probabilities(&draws, matrices.clone(), 500, 7, 3);
+            let other_seed = win_probabilities(&draws, matrices, 500, 8, 3);
+
+            for (a, b) in single.iter().zip(multi.iter()) {
+                assert!((a.first - b.first).abs() < 1e-9);
+                assert!((a.last - b.last).abs() < 1e-9);
+            }
+            assert_ne!(single, other_seed);
+        }
+
+        #[test]
+        fn identical_boards_split_evenly() {
+            let (draws, matrices) = parse_input_string(EXAMPLE_INPUT);
+
+            let result = win_probabilities(&draws, vec![matrices[0], matrices[0]], 100, 3, 2);
+
+            for probability in result {
+                assert_eq!(probability.first, 0.5);
+                assert_eq!(probability.last, 0.5);
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "There should be at least one trial")]
+        fn no_trials() {
+            let (draws, matrices) = parse_input_string(EXAMPLE_INPUT);
+
+            win_probabilities(&draws, matrices, 0, 1, 1);
+        }
+
+        #[test]
+        fn solution() {
+            let (draws, matrices) = parse_input_string(&parse_input());
+            let num_boards = matrices.len();
+
+            let result = win_probabilities(&draws, matrices, 2000, 2021, 8);
+
+            assert_eq!(result.len(), num_boards);
+            // With this many boards no single one should dominate.
+            assert!(result.iter().all(|p| p.first < 0.1 && p.last < 0.1));
+        }
+    }
+
+    mod draws_for_target_to_win {
+        use std::collections::HashSet;
+
+        use crate::day_04::{
+            bingo_first_to_win_score, draws_for_target_to_win,
+            tests::{parse_input, parse_input_string, EXAMPLE_INPUT},
+            BingoBoard,
+        };
+
+        fn expected_score(matrix: [[u8; 5]; 5], draws: &[u8]) -> u64 {
+            let board = BingoBoard::new(matrix);
+            let marks = board.winning_mark_indices(draws).unwrap();
+            board.score(marks, *draws.last().unwrap())
+        }
+
+        #[test]
+        fn example_test() {
+            let (_, matrices) = parse_input_string(EXAMPLE_INPUT);
+
+            for target in 0..matrices.len() {
+                let draws = draws_for_target_to_win(matrices.clone(), target).unwrap();
+
+                assert_eq!(draws.len(), 5);
+                assert_eq!(
+                    bingo_first_to_win_score(draws.clone(), matrices.clone()),
+                    expected_score(matrices[target], &draws),
+                    "failed for target {}",
+                    target
+                );
+            }
+        }
+
+        #[test]
+        fn tie_test() {
+            let (_, matrices) = parse_input_string(EXAMPLE_INPUT);
+            let matrices = vec![matrices[0], matrices[1], matrices[0]];
+
+            // Boards 0 and 2 are identical, so they always win together.
+            assert_eq!(
+                draws_for_target_to_win(matrices.clone(), 0),
+                Err(vec![2; 10])
+            );
+            assert_eq!(
+                draws_for_target_to_win(matrices.clone(), 2),
+                Err(vec![0; 10])
+            );
+
+            // On a tie, the earlier board is scored.
+            let draws = matrices[0][0].to_vec();
+            assert_eq!(
+                bingo_first_to_win_score(draws.clone(), matrices.clone()),
+                expected_score(matrices[0], &draws),
+            );
+
+            assert!(draws_for_target_to_win(matrices, 1).is_ok());
+        }
+
+        #[test]
+        fn blocked_by_different_boards_test() {
+            let target = [
+                [1, 2, 3, 4, 5],
+                [6, 7, 8, 9, 10],
+                [11, 12, 13, 14, 15],
+                [16, 17, 18, 19, 20],
+                [21, 22, 23, 24, 25],
+            ];
+            let mut rows_blocker = target;
+            rows_blocker[0] = [26, 27, 28, 29, 30];
+            let columns_blocker = [
+                [1, 6, 11, 16, 21],
+                [26, 31, 36, 41, 46],
+                [27, 32, 37, 42, 47],
+                [28, 33, 38, 43, 48],
+                [29, 34, 39, 44, 49],
+            ];
+
+            // Only the first row is free of a line from the other boards.
+            assert_eq!(
+                draws_for_target_to_win(vec![rows_blocker, target], 1),
+                Ok(vec![1, 2, 3, 4, 5])
+            );
+
+            // `columns_blocker` has the first column of `target` as a row, and
+            // `transposed` shares every line with `target`. The first board found
+            // is reported.
+            let transposed = [0, 1, 2, 3, 4].map(|i| target.map(|row| row[i]));
+            let result =
+                draws_for_target_to_win(vec![target, rows_blocker, columns_blocker, transposed], 0);
+            assert_eq!(result, Err(vec![3, 1, 1, 1, 1, 2, 3, 3, 3, 3]));
+        }
+
+        #[test]
+        fn solution() {
+            let (_, matrices) = parse_input_string(&parse_input());
+
+            for target in 0..matrices.len() {
+                if let Ok(draws) = draws_for_target_to_win(matrices.clone(), target) {
+                    assert_eq!(HashSet::<u8>::from_iter(draws.clone()).len(), 5);
+                    assert_eq!(
+                        bingo_first_to_win_score(draws.clone(), matrices.clone()),
+                        expected_score(matrices[target], &draws),
+                    );
+                }
+            }
+        }
+    }
 }
@@ -1,4 +1,9 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::{Add, Mul},
+};
+
+use crate::common::{big_uint::BigUint, rng::Rng};
 
 // Every 7 days, a fish produces another fish.
 //
@@ -9,101 +14,615 @@ use std::collections::{HashMap, VecDeque};
 // Day 7 => 2 fish
 //
 // A new fish takes 7 + 2 days to produce a fish for the first cycle.
+//
+// `PopulationModel` generalises these numbers, see `LANTERNFISH` for the ones
+// above.
 
-const DAYS_TO_REPRODUCE: i64 = 7;
-const DAYS_TO_REPRODUCE_FIRST_CYCLE: i64 = DAYS_TO_REPRODUCE + 2;
+/// How a population of fish grows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PopulationModel {
+    /// Days between litters of an adult fish.
+    cycle_length: i64,
+    /// Extra days a newborn fish takes before its first litter.
+    first_cycle_delay: i64,
+    /// Number of fishes born in each litter.
+    litter_size: i64,
+    /// Age in days at which a fish dies. A fish dying on a day does not
+    /// reproduce on that day.
+    lifespan: Option<i64>,
+}
 
 #[allow(dead_code)]
-fn laternfish_after_n_days_brute_force(fishes: &Vec<i64>, n: i64) -> i64 {
-    let mut fishes = fishes.clone();
+const LANTERNFISH: PopulationModel = PopulationModel {
+    cycle_length: 7,
+    first_cycle_delay: 2,
+    litter_size: 1,
+    lifespan: None,
+};
+
+impl PopulationModel {
+    #[allow(dead_code)]
+    fn new(cycle_length: i64, first_cycle_delay: i64, litter_size: i64) -> Self {
+        assert!(cycle_length > 0, "Cycle length must be positive");
+        assert!(
+            first_cycle_delay >= 0,
+            "First cycle delay must not be negative"
+        );
+        assert!(litter_size >= 0, "Litter size must not be negative");
+
+        Self {
+            cycle_length,
+            first_cycle_delay,
+            litter_size,
+            lifespan: None,
+        }
+    }
+
+    /// Fishes must live through their first cycle, so that every timer is a
+    /// fish that is still alive.
+    #[allow(dead_code)]
+    fn with_lifespan(self, lifespan: i64) -> Self {
+        assert!(
+            lifespan >= self.first_cycle_length(),
+            "Lifespan must be at least the first cycle length of {}",
+            self.first_cycle_length()
+        );
+
+        Self {
+            lifespan: Some(lifespan),
+            ..self
+        }
+    }
+
+    /// Days a newborn fish takes to produce its first litter.
+    fn first_cycle_length(&self) -> i64 {
+        self.cycle_length + self.first_cycle_delay
+    }
+
+    /// Timers go from 0 to `first_cycle_length() - 1` inclusive.
+    fn num_timers(&self) -> usize {
+        self.first_cycle_length() as usize
+    }
+
+    /// The age of a fish with `timer`.
+    ///
+    /// The timer of an adult fish doesn't say how many cycles it has been
+    /// through, so this is the youngest age the fish could be.
+    fn age_of_timer(&self, timer: i64) -> i64 {
+        assert!(
+            (0..self.first_cycle_length()).contains(&timer),
+            "Timers should go from only 0 to {}",
+            self.first_cycle_length() - 1
+        );
+
+        self.first_cycle_length() - 1 - timer
+    }
+
+    fn is_alive_at(&self, age: i64) -> bool {
+        self.lifespan.is_none_or(|lifespan| age < lifespan)
+    }
+
+    /// The first age after `age` at which a fish produces a litter.
+    fn next_reproduction_age(&self, age: i64) -> i64 {
+        if age < self.first_cycle_length() {
+            self.first_cycle_length()
+        } else {
+            self.first_cycle_length()
+                + ((age - self.first_cycle_length()) / self.cycle_length + 1) * self.cycle_length
+        }
+    }
+
+    /// Number of buckets used by the bucketed simulators. Fishes are bucketed
+    /// by timer, or by age if they can die.
+    fn num_buckets(&self) -> usize {
+        match self.lifespan {
+            None => self.num_timers(),
+            Some(lifespan) => lifespan as usize,
+        }
+    }
+
+    fn bucket_of_timer(&self, timer: i64) -> usize {
+        match self.lifespan {
+            None => self.num_timers() - 1 - self.age_of_timer(timer) as usize,
+            Some(_) => self.age_of_timer(timer) as usize,
+        }
+    }
+
+    /// Counts the fishes in each bucket.
+    fn buckets(&self, fishes: &[i64]) -> VecDeque<i64> {
+        fishes.iter().fold(
+            VecDeque::from_iter(vec![0; self.num_buckets()]),
+            |mut counts, current_fish| {
+                counts[self.bucket_of_timer(*current_fish)] += 1;
+                counts
+            },
+        )
+    }
+
+    /// Advances the counts from `buckets` by one day.
+    fn advance(&self, counts: &mut VecDeque<i64>) {
+        match self.lifespan {
+            None => {
+                let new_parents = counts.pop_front().unwrap();
+                counts.push_back(new_parents * self.litter_size);
+                counts[self.cycle_length as usize - 1] += new_parents;
+            }
+            Some(_) => {
+                // Everyone gets a day older and the oldest die.
+                counts.pop_back();
+                counts.push_front(0);
+
+                let new_parents = (self.first_cycle_length() as usize..counts.len())
+                    .step_by(self.cycle_length as usize)
+                    .map(|age| counts[age])
+                    .sum::<i64>();
+                counts[0] = new_parents * self.litter_size;
+            }
+        }
+    }
+}
+
+/// A fish in the brute force simulation.
+#[derive(Debug, Clone, Copy)]
+struct Fish {
+    timer: i64,
+    age: i64,
+}
+
+#[allow(dead_code)]
+fn laternfish_after_n_days_brute_force(model: &PopulationModel, fishes: &[i64], n: i64) -> i64 {
+    let mut fishes = to_fishes(model, fishes);
 
     for _ in 0..n {
-        update_one_day(&mut fishes);
+        update_one_day(model, &mut fishes, &mut || model.cycle_length);
     }
 
     fishes.len() as i64
 }
 
-fn update_one_day(fishes: &mut Vec<i64>) {
-    let mut new_timers = Vec::new();
+fn to_fishes(model: &PopulationModel, timers: &[i64]) -> Vec<Fish> {
+    timers
+        .iter()
+        .map(|timer| Fish {
+            timer: *timer,
+            age: model.age_of_timer(*timer),
+        })
+        .collect()
+}
+
+/// Advances `fishes` by one day. Every time a fish starts a new cycle, its
+/// length is taken from `next_cycle_length`.
+fn update_one_day(
+    model: &PopulationModel,
+    fishes: &mut Vec<Fish>,
+    next_cycle_length: &mut impl FnMut() -> i64,
+) {
+    let mut new_fishes = 0;
 
-    for timer in fishes.iter_mut() {
-        if *timer == 0 {
-            *timer = DAYS_TO_REPRODUCE - 1;
-            new_timers.push(DAYS_TO_REPRODUCE_FIRST_CYCLE - 1);
-            continue;
+    fishes.retain_mut(|fish| {
+        fish.age += 1;
+        if !model.is_alive_at(fish.age) {
+            return false;
         }
 
-        let new_timer = *timer - 1;
-        *timer = new_timer;
+        if fish.timer == 0 {
+            fish.timer = next_cycle_length() - 1;
+            new_fishes += model.litter_size;
+        } else {
+            fish.timer -= 1;
+        }
+        true
+    });
+
+    fishes.extend((0..new_fishes).map(|_| Fish {
+        timer: next_cycle_length() + model.first_cycle_delay - 1,
+        age: 0,
+    }));
+}
+
+/// How much each reproduction cycle deviates from
+/// [`PopulationModel::cycle_length`] in the stochastic simulation.
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+enum CycleJitter {
+    /// Every cycle has exactly the model's length.
+    None,
+    /// Uniformly distributed in `-spread..=spread` days.
+    Uniform(i64),
+    /// `(deviation, weight)` pairs, where each deviation is drawn with
+    /// probability proportional to its weight.
+    Weighted(Vec<(i64, u64)>),
+}
+
+impl CycleJitter {
+    /// Panics if the jitter cannot be sampled.
+    fn validate(&self) {
+        match self {
+            CycleJitter::None => {}
+            CycleJitter::Uniform(spread) => {
+                assert!(*spread >= 0, "Uniform spread should not be negative")
+            }
+            CycleJitter::Weighted(weights) => assert!(
+                weights.iter().any(|(_, weight)| *weight > 0),
+                "Weighted jitter needs at least one positive weight"
+            ),
+        }
     }
 
-    fishes.extend(new_timers.into_iter());
+    /// Draws the length of a cycle, which is at least 1 day.
+    fn cycle_length(&self, model: &PopulationModel, rng: &mut Rng) -> i64 {
+        let deviation = match self {
+            CycleJitter::None => 0,
+            CycleJitter::Uniform(spread) => rng.below(2 * *spread as u64 + 1) as i64 - spread,
+            CycleJitter::Weighted(weights) => {
+                let total = weights.iter().map(|(_, weight)| weight).sum::<u64>();
+                let mut target = rng.below(total);
+
+                weights
+                    .iter()
+                    .find(|(_, weight)| {
+                        if target < *weight {
+                            true
+                        } else {
+                            target -= weight;
+                            false
+                        }
+                    })
+                    .map(|(deviation, _)| *deviation)
+                    .unwrap()
+            }
+        };
+
+        (model.cycle_length + deviation).max(1)
+    }
 }
 
+/// Summary of the population on one day over all trials.
+#[derive(Debug, Clone, PartialEq)]
+struct DailyStatistics {
+    day: i64,
+    mean: f64,
+    variance: f64,
+    /// The requested percentiles, in the same order.
+    percentiles: Vec<i64>,
+}
+
+/// Runs the brute force simulation `trials` times for days `0..=n`, drawing
+/// every cycle length from `jitter`, and summarises the population per day.
+///
+/// Trial `i` is seeded from `(seed, i)`. Percentiles are in `0..=100` and use
+/// the nearest-rank method.
 #[allow(dead_code)]
-fn laternfish_after_n_days(fishes: &Vec<i64>, n: i64) -> i64 {
-    let mut cache = HashMap::new();
+fn laternfish_after_n_days_stochastic(
+    model: &PopulationModel,
+    jitter: &CycleJitter,
+    fishes: &[i64],
+    n: i64,
+    trials: usize,
+    seed: u64,
+    percentiles: &[u8],
+) -> Vec<DailyStatistics> {
+    assert!(trials > 0, "There should be at least one trial");
+    jitter.validate();
+    assert!(
+        percentiles.iter().all(|p| *p <= 100),
+        "Percentiles should be from 0 to 100"
+    );
+
+    // `populations[day][trial]`.
+    let mut populations = vec![Vec::with_capacity(trials); n as usize + 1];
+
+    for trial in 0..trials {
+        let mut rng = Rng::with_stream(seed, trial as u64);
+        let mut fishes = to_fishes(model, fishes);
+
+        for (day, totals) in populations.iter_mut().enumerate() {
+            if day > 0 {
+                update_one_day(model, &mut fishes, &mut || {
+                    jitter.cycle_length(model, &mut rng)
+                });
+            }
+            totals.push(fishes.len() as i64);
+        }
+    }
 
-    fishes
+    populations
         .into_iter()
-        .fold(0, |acc, fish| acc + count_fishes(&mut cache, n, *fish))
+        .enumerate()
+        .map(|(day, mut totals)| {
+            totals.sort();
+
+            let mean = totals.iter().sum::<i64>() as f64 / trials as f64;
+            let variance = totals
+                .iter()
+                .map(|total| (*total as f64 - mean).powi(2))
+                .sum::<f64>()
+                / trials as f64;
+
+            let percentiles = percentiles
+                .iter()
+                .map(|p| {
+                    let rank = (*p as usize * trials).div_ceil(100).max(1);
+                    totals[rank - 1]
+                })
+                .collect();
+
+            DailyStatistics {
+                day: day as i64,
+                mean,
+                variance,
+                percentiles,
+            }
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+fn laternfish_after_n_days(model: &PopulationModel, fishes: &[i64], n: i64) -> i64 {
+    let mut cache = HashMap::new();
+
+    fishes.iter().fold(0, |acc, fish| {
+        acc + count_fishes(model, &mut cache, n, model.age_of_timer(*fish))
+    })
 }
 
 // I did not think of this.
 #[allow(dead_code)]
-fn laternfish_after_n_days_simple(fishes: &Vec<i64>, n: i64) -> i64 {
-    let mut counts = fishes.into_iter().fold(
-        // Plus one because counts range from 0 to 8 inclusive.
-        VecDeque::from_iter(vec![0; DAYS_TO_REPRODUCE_FIRST_CYCLE as usize]),
-        |mut counts, current_fish| {
-            *counts
-                .get_mut(*current_fish as usize)
-                .expect("Timers should go from only 0 to 8") += 1;
-            counts
-        },
-    );
+fn laternfish_after_n_days_simple(model: &PopulationModel, fishes: &[i64], n: i64) -> i64 {
+    let mut counts = model.buckets(fishes);
 
     for _ in 0..n {
-        let new_parents = counts.pop_front().unwrap();
-        *counts.get_mut(DAYS_TO_REPRODUCE as usize - 1).unwrap() += new_parents;
-        counts.push_back(new_parents);
+        model.advance(&mut counts);
     }
 
     counts.into_iter().sum()
 }
 
-/// Counts the fishes that will exist in the pool after t, given a fish with `fish_time`.
-fn count_fishes(cache: &mut HashMap<(i64, i64), i64>, t: i64, time_to_reproduction: i64) -> i64 {
-    let memoize_key = (t, time_to_reproduction);
-    if cache.contains_key(&memoize_key) {
-        return *cache.get(&memoize_key).unwrap();
+/// The population at the start of a day.
+#[derive(Debug, Clone, PartialEq)]
+struct PopulationSnapshot {
+    day: i64,
+    total: i64,
+    /// Fishes in each bucket, see [`PopulationModel::num_buckets`].
+    buckets: Vec<i64>,
+}
+
+/// Infinite iterator over the population on day 0, 1, 2, ...
+///
+/// Steps the same bucket rotation as [`laternfish_after_n_days_simple`], so it
+/// panics once the total overflows an `i64`.
+struct PopulationSeries<'a> {
+    model: &'a PopulationModel,
+    counts: VecDeque<i64>,
+    day: i64,
+}
+
+impl<'a> PopulationSeries<'a> {
+    fn new(model: &'a PopulationModel, fishes: &[i64]) -> Self {
+        Self {
+            model,
+            counts: model.buckets(fishes),
+            day: 0,
+        }
     }
-    assert!(time_to_reproduction <= 8);
-    assert!(time_to_reproduction >= 0);
+}
+
+impl Iterator for PopulationSeries<'_> {
+    type Item = PopulationSnapshot;
 
-    let mut result = 1;
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = PopulationSnapshot {
+            day: self.day,
+            total: self.counts.iter().sum(),
+            buckets: self.counts.iter().copied().collect(),
+        };
 
-    // Child is only produced when current_t is < 0, not when it is at 0.
-    let time_to_produce_first_child = time_to_reproduction + 1;
+        self.model.advance(&mut self.counts);
+        self.day += 1;
 
-    let remaining_time_for_children = t - time_to_produce_first_child;
-    if remaining_time_for_children < 0 {
-        return result;
+        Some(result)
     }
+}
+
+/// The first day the population is larger than `threshold`, or `None` if it
+/// never will be.
+///
+/// A population that stays at or below `threshold` only has finitely many
+/// states, so it must eventually repeat one. This is detected with Brent's
+/// cycle detection, comparing against a saved state that is replaced whenever
+/// the number of days since it was saved reaches a power of two.
+#[allow(dead_code)]
+fn first_day_exceeding(model: &PopulationModel, fishes: &[i64], threshold: i64) -> Option<i64> {
+    let mut saved: Option<Vec<i64>> = None;
+    let mut power = 1;
+    let mut days_since_saved = 0;
 
-    // If remaining_time_for_children < DAYS_TO_REPRODUCE, there will be zero
-    // generations. Add 1 for the first generation.
-    let children_generations = remaining_time_for_children / DAYS_TO_REPRODUCE + 1;
+    for snapshot in PopulationSeries::new(model, fishes) {
+        if snapshot.total > threshold {
+            return Some(snapshot.day);
+        }
 
-    for i in 0..children_generations {
-        let time_for_generation = remaining_time_for_children - i * DAYS_TO_REPRODUCE;
+        if saved.as_ref() == Some(&snapshot.buckets) {
+            return None;
+        }
 
-        result += count_fishes(
-            cache,
-            time_for_generation,
-            DAYS_TO_REPRODUCE_FIRST_CYCLE - 1,
-        );
+        days_since_saved += 1;
+        if saved.is_none() || days_since_saved == power {
+            saved = Some(snapshot.buckets);
+            power *= 2;
+            days_since_saved = 0;
+        }
+    }
+
+    unreachable!("Population series should be infinite")
+}
+
+/// The average daily growth factor between day `from` and day `to`, i.e. the
+/// `x` such that `population(to) = population(from) * x^(to - from)`.
+///
+/// Returns `None` if the population is 0 on day `from`.
+#[allow(dead_code)]
+fn growth_rate(model: &PopulationModel, fishes: &[i64], from: i64, to: i64) -> Option<f64> {
+    assert!(0 <= from && from < to, "Days should be in increasing order");
+
+    let mut series = PopulationSeries::new(model, fishes).skip(from as usize);
+    let from_total = series.next().unwrap().total;
+    let to_total = series.nth((to - from - 1) as usize).unwrap().total;
+
+    if from_total == 0 {
+        return None;
+    }
+
+    Some((to_total as f64 / from_total as f64).powf(1.0 / (to - from) as f64))
+}
+
+/// Same as [`laternfish_after_n_days_simple`], but as `counts * M^n` where `M`
+/// is the matrix that advances the counts by one day, so it takes O(log n)
+/// matrix multiplications.
+///
+/// The count grows by roughly 10% a day, so the exact answer has about
+/// `n / 25` digits. Use [`laternfish_after_n_days_modulo`] for very large `n`.
+#[allow(dead_code)]
+fn laternfish_after_n_days_exact(model: &PopulationModel, fishes: &[i64], n: u64) -> BigUint {
+    laternfish_after_n_days_matrix(model, fishes, n, |x| BigUint::from(x as u64))
+}
+
+/// The number of fishes after `n` days modulo `modulus`, for `n` up to
+/// `u64::MAX`.
+#[allow(dead_code)]
+fn laternfish_after_n_days_modulo(
+    model: &PopulationModel,
+    fishes: &[i64],
+    n: u64,
+    modulus: u64,
+) -> u64 {
+    assert!(modulus > 0, "Modulus must be positive");
+
+    laternfish_after_n_days_matrix(model, fishes, n, |x| Modular::new(x as u64, modulus)).value
+}
+
+fn laternfish_after_n_days_matrix<T>(
+    model: &PopulationModel,
+    fishes: &[i64],
+    n: u64,
+    lift: impl Fn(i64) -> T,
+) -> T
+where
+    T: Clone + Add<Output = T> + Mul<Output = T>,
+{
+    let zero = lift(0);
+    let size = model.num_buckets();
+
+    // `transition[from][to]` is the number of fishes in bucket `to` that a
+    // fish in bucket `from` turns into after one day.
+    let mut transition = (0..size)
+        .map(|from| {
+            let mut counts = VecDeque::from_iter(vec![0; size]);
+            counts[from] = 1;
+            model.advance(&mut counts);
+            counts.into_iter().map(&lift).collect::<Vec<T>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut counts = vec![model
+        .buckets(fishes)
+        .into_iter()
+        .map(&lift)
+        .collect::<Vec<T>>()];
+
+    let mut power = n;
+    while power > 0 {
+        if power & 1 == 1 {
+            counts = matrix_multiply(&counts, &transition, &zero);
+        }
+        transition = matrix_multiply(&transition, &transition, &zero);
+        power >>= 1;
+    }
+
+    counts
+        .remove(0)
+        .into_iter()
+        .fold(zero, |acc, count| acc + count)
+}
+
+fn matrix_multiply<T>(a: &[Vec<T>], b: &[Vec<T>], zero: &T) -> Vec<Vec<T>>
+where
+    T: Clone + Add<Output = T> + Mul<Output = T>,
+{
+    a.iter()
+        .map(|row| {
+            (0..b[0].len())
+                .map(|j| {
+                    row.iter()
+                        .zip(b.iter())
+                        .fold(zero.clone(), |acc, (x, b_row)| {
+                            acc + x.clone() * b_row[j].clone()
+                        })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// A count modulo `modulus`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Modular {
+    value: u64,
+    modulus: u64,
+}
+
+impl Modular {
+    fn new(value: u64, modulus: u64) -> Self {
+        Self {
+            value: value % modulus,
+            modulus,
+        }
+    }
+}
+
+impl Add for Modular {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(
+            ((self.value as u128 + other.value as u128) % self.modulus as u128) as u64,
+            self.modulus,
+        )
+    }
+}
+
+impl Mul for Modular {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            ((self.value as u128 * other.value as u128) % self.modulus as u128) as u64,
+            self.modulus,
+        )
+    }
+}
+
+/// Counts the fishes that will exist in the pool after t, given a fish of `age`.
+fn count_fishes(
+    model: &PopulationModel,
+    cache: &mut HashMap<(i64, i64), i64>,
+    t: i64,
+    age: i64,
+) -> i64 {
+    let memoize_key = (t, age);
+    if let Some(result) = cache.get(&memoize_key) {
+        return *result;
+    }
+
+    let mut result = model.is_alive_at(age + t) as i64;
+
+    let mut reproduction_age = model.next_reproduction_age(age);
+    while reproduction_age <= age + t && model.is_alive_at(reproduction_age) {
+        let time_for_generation = age + t - reproduction_age;
+
+        result += model.litter_size * count_fishes(model, cache, time_for_generation, 0);
+
+        reproduction_age += model.cycle_length;
     }
 
     cache.insert(memoize_key, result);
@@ -128,15 +647,17 @@ mod tests {
     const EXAMPLE: [i64; 5] = [3, 4, 3, 1, 2];
 
     mod part_1 {
-        use crate::day_06::{laternfish_after_n_days_brute_force, laternfish_after_n_days_simple};
+        use crate::day_06::{
+            laternfish_after_n_days_brute_force, laternfish_after_n_days_simple, LANTERNFISH,
+        };
 
         use super::super::laternfish_after_n_days;
         use super::{parse_input, EXAMPLE};
 
         #[test]
         fn empty_test() {
-            assert_eq!(laternfish_after_n_days(&Vec::new(), 10), 0,);
-            assert_eq!(laternfish_after_n_days(&vec![8], 8), 1,);
+            assert_eq!(laternfish_after_n_days(&LANTERNFISH, &[], 10), 0,);
+            assert_eq!(laternfish_after_n_days(&LANTERNFISH, &[8], 8), 1,);
         }
 
         #[test]
@@ -166,15 +687,15 @@ mod tests {
                 (vec![6], 16),
             ] {
                 assert_eq!(
-                    laternfish_after_n_days(&fishes, days),
-                    laternfish_after_n_days_brute_force(&fishes, days),
+                    laternfish_after_n_days(&LANTERNFISH, &fishes, days),
+                    laternfish_after_n_days_brute_force(&LANTERNFISH, &fishes, days),
                     "failed for {:?}",
                     (fishes, days)
                 );
 
                 assert_eq!(
-                    laternfish_after_n_days_simple(&fishes, days),
-                    laternfish_after_n_days_brute_force(&fishes, days),
+                    laternfish_after_n_days_simple(&LANTERNFISH, &fishes, days),
+                    laternfish_after_n_days_brute_force(&LANTERNFISH, &fishes, days),
                     "failed for {:?}",
                     (fishes, days)
                 );
@@ -183,23 +704,23 @@ mod tests {
 
         #[test]
         fn example_test() {
-            assert_eq!(
-                laternfish_after_n_days(&EXAMPLE.into_iter().collect(), 80),
-                5934,
-            );
+            assert_eq!(laternfish_after_n_days(&LANTERNFISH, &EXAMPLE, 80), 5934,);
         }
 
         #[test]
         fn solution() {
             let input = parse_input();
-            assert_eq!(laternfish_after_n_days(&input, 80), 366057,);
+            assert_eq!(laternfish_after_n_days(&LANTERNFISH, &input, 80), 366057,);
 
-            assert_eq!(laternfish_after_n_days_brute_force(&input, 80), 366057,);
+            assert_eq!(
+                laternfish_after_n_days_brute_force(&LANTERNFISH, &input, 80),
+                366057,
+            );
         }
     }
 
     mod part_2 {
-        use crate::day_06::laternfish_after_n_days_simple;
+        use crate::day_06::{laternfish_after_n_days_simple, LANTERNFISH};
 
         use super::super::laternfish_after_n_days;
         use super::{parse_input, EXAMPLE};
@@ -209,7 +730,7 @@ mod tests {
         #[test]
         fn example_test() {
             assert_eq!(
-                laternfish_after_n_days(&EXAMPLE.into_iter().collect(), 256),
+                laternfish_after_n_days(&LANTERNFISH, &EXAMPLE, 256),
                 26984457539,
             );
         }
@@ -217,9 +738,436 @@ mod tests {
         #[test]
         fn solution() {
             let input = parse_input();
-            assert_eq!(laternfish_after_n_days(&input, 256), 1653559299811,);
+            assert_eq!(
+                laternfish_after_n_days(&LANTERNFISH, &input, 256),
+                1653559299811,
+            );
 
-            assert_eq!(laternfish_after_n_days_simple(&input, 256), 1653559299811,);
+            assert_eq!(
+                laternfish_after_n_days_simple(&LANTERNFISH, &input, 256),
+                1653559299811,
+            );
+        }
+    }
+
+    mod matrix {
+        use std::collections::VecDeque;
+
+        use crate::common::big_uint::BigUint;
+        use crate::day_06::{
+            laternfish_after_n_days, laternfish_after_n_days_brute_force,
+            laternfish_after_n_days_exact, laternfish_after_n_days_modulo,
+            laternfish_after_n_days_simple, LANTERNFISH,
+        };
+
+        use super::{parse_input, EXAMPLE};
+
+        const PRIME: u64 = 1_000_000_007;
+
+        #[test]
+        fn small_test() {
+            let fishes = EXAMPLE
+                .into_iter()
+                .chain(vec![0, 5, 6, 8])
+                .collect::<Vec<_>>();
+
+            for days in 0..=100 {
+                let expected = laternfish_after_n_days_simple(&LANTERNFISH, &fishes, days);
+                assert_eq!(
+                    laternfish_after_n_days(&LANTERNFISH, &fishes, days),
+                    expected
+                );
+                if days <= 60 {
+                    assert_eq!(
+                        laternfish_after_n_days_brute_force(&LANTERNFISH, &fishes, days),
+                        expected
+                    );
+                }
+
+                assert_eq!(
+                    laternfish_after_n_days_exact(&LANTERNFISH, &fishes, days as u64),
+                    BigUint::from(expected as u64),
+                    "failed for {} days",
+                    days
+                );
+                assert_eq!(
+                    laternfish_after_n_days_modulo(&LANTERNFISH, &fishes, days as u64, PRIME),
+                    expected as u64 % PRIME,
+                    "failed for {} days",
+                    days
+                );
+            }
+        }
+
+        #[test]
+        fn empty_test() {
+            assert_eq!(
+                laternfish_after_n_days_exact(&LANTERNFISH, &[], 100),
+                BigUint::zero()
+            );
+            assert_eq!(
+                laternfish_after_n_days_modulo(&LANTERNFISH, &[], 100, PRIME),
+                0
+            );
+        }
+
+        #[test]
+        fn large_exact_test() {
+            // Far beyond what fits in an `i64`.
+            assert_eq!(
+                laternfish_after_n_days_exact(&LANTERNFISH, &EXAMPLE, 1000).to_string(),
+                "379589061144698259131825683795505058481"
+            );
+        }
+
+        #[test]
+        fn large_modulo_test() {
+            let days = 100_000;
+
+            // The `VecDeque` rotation from `laternfish_after_n_days_simple`,
+            // with every addition taken modulo `PRIME`.
+            let mut counts = VecDeque::from(vec![0u64; 9]);
+            for fish in EXAMPLE {
+                counts[fish as usize] += 1;
+            }
+            for _ in 0..days {
+                let new_parents = counts.pop_front().unwrap();
+                counts[6] = (counts[6] + new_parents) % PRIME;
+                counts.push_back(new_parents);
+            }
+            let expected = counts.into_iter().sum::<u64>() % PRIME;
+
+            assert_eq!(
+                laternfish_after_n_days_modulo(&LANTERNFISH, &EXAMPLE, days, PRIME),
+                expected
+            );
+            assert!(
+                laternfish_after_n_days_modulo(&LANTERNFISH, &EXAMPLE, 10u64.pow(18), PRIME)
+                    < PRIME
+            );
+        }
+
+        #[test]
+        fn solution() {
+            let input = parse_input();
+            assert_eq!(
+                laternfish_after_n_days_exact(&LANTERNFISH, &input, 256),
+                BigUint::from(1653559299811)
+            );
+        }
+    }
+
+    mod population_model {
+        use crate::common::big_uint::BigUint;
+        use crate::day_06::{
+            laternfish_after_n_days, laternfish_after_n_days_brute_force,
+            laternfish_after_n_days_exact, laternfish_after_n_days_modulo,
+            laternfish_after_n_days_simple, PopulationModel, LANTERNFISH,
+        };
+
+        fn assert_all_agree(model: &PopulationModel, fishes: &[i64], max_days: i64) {
+            for days in 0..=max_days {
+                let expected = laternfish_after_n_days_brute_force(model, fishes, days);
+                let failure = format!("failed for {:?} after {} days", model, days);
+
+                assert_eq!(
+                    laternfish_after_n_days(model, fishes, days),
+                    expected,
+                    "{}",
+                    failure
+                );
+                assert_eq!(
+                    laternfish_after_n_days_simple(model, fishes, days),
+                    expected,
+                    "{}",
+                    failure
+                );
+                assert_eq!(
+                    laternfish_after_n_days_exact(model, fishes, days as u64),
+                    BigUint::from(expected as u64),
+                    "{}",
+                    failure
+                );
+                assert_eq!(
+                    laternfish_after_n_days_modulo(model, fishes, days as u64, 97),
+                    expected as u64 % 97,
+                    "{}",
+                    failure
+                );
+            }
+        }
+
+        #[test]
+        fn lanternfish_test() {
+            assert_eq!(PopulationModel::new(7, 2, 1), LANTERNFISH);
+        }
+
+        #[test]
+        fn custom_cycle_test() {
+            // Reproduce every 5 days with a 3 day juvenile delay.
+            let model = PopulationModel::new(5, 3, 1);
+
+            assert_all_agree(&model, &[0, 3, 4, 7], 50);
+            assert_eq!(laternfish_after_n_days_simple(&model, &[7], 8), 2);
+            assert_eq!(laternfish_after_n_days_simple(&model, &[7], 13), 3);
+            assert_eq!(laternfish_after_n_days_simple(&model, &[7], 16), 4);
+        }
+
+        #[test]
+        fn litter_size_test() {
+            let model = PopulationModel::new(7, 2, 3);
+
+            assert_all_agree(&model, &[3, 4, 3, 1, 2], 40);
+            assert_eq!(laternfish_after_n_days_simple(&model, &[0], 1), 4);
+        }
+
+        #[test]
+        fn no_delay_test() {
+            let model = PopulationModel::new(1, 0, 1);
+
+            // Doubles every day.
+            assert_all_agree(&model, &[0], 20);
+            assert_eq!(laternfish_after_n_days(&model, &[0], 10), 1024);
+        }
+
+        #[test]
+        fn lifespan_test() {
+            let model = LANTERNFISH.with_lifespan(20);
+
+            assert_all_agree(&model, &[3, 4, 3, 1, 2, 8], 60);
+
+            // Reproduces at 9 and 16 and dies at 20. The first child
+            // reproduces at 18.
+            assert_eq!(laternfish_after_n_days(&model, &[8], 19), 4);
+            assert_eq!(laternfish_after_n_days(&model, &[8], 20), 3);
+        }
+
+        #[test]
+        fn dies_before_reproducing_test() {
+            let model = LANTERNFISH.with_lifespan(9);
+
+            assert_all_agree(&model, &[0, 4, 8], 20);
+            assert_eq!(laternfish_after_n_days(&model, &[8], 9), 0);
+        }
+
+        #[test]
+        #[should_panic(expected = "Lifespan must be at least the first cycle length of 9")]
+        fn short_lifespan_test() {
+            LANTERNFISH.with_lifespan(5);
+        }
+
+        #[test]
+        #[should_panic(expected = "Timers should go from only 0 to 7")]
+        fn invalid_timer_test() {
+            laternfish_after_n_days(&PopulationModel::new(5, 3, 1), &[8], 1);
+        }
+    }
+
+    mod population_series {
+        use crate::day_06::{
+            first_day_exceeding, growth_rate, laternfish_after_n_days_simple, PopulationModel,
+            PopulationSeries, LANTERNFISH,
+        };
+
+        use super::{parse_input, EXAMPLE};
+
+        #[test]
+        fn example_test() {
+            let snapshots = PopulationSeries::new(&LANTERNFISH, &EXAMPLE)
+                .take(19)
+                .collect::<Vec<_>>();
+
+            assert_eq!(snapshots[0].day, 0);
+            assert_eq!(snapshots[0].total, 5);
+            assert_eq!(snapshots[0].buckets, vec![0, 1, 1, 2, 1, 0, 0, 0, 0]);
+
+            assert_eq!(snapshots[11].total, 15);
+            assert_eq!(snapshots[11].buckets, vec![2, 2, 1, 0, 1, 1, 4, 1, 3]);
+
+            assert_eq!(snapshots[18].day, 18);
+            assert_eq!(snapshots[18].total, 26);
+        }
+
+        #[test]
+        fn matches_simple_test() {
+            let input = parse_input();
+
+            for snapshot in PopulationSeries::new(&LANTERNFISH, &input).take(257) {
+                assert_eq!(
+                    snapshot.total,
+                    laternfish_after_n_days_simple(&LANTERNFISH, &input, snapshot.day)
+                );
+                assert_eq!(snapshot.total, snapshot.buckets.iter().sum::<i64>());
+            }
+        }
+
+        #[test]
+        fn first_day_exceeding_test() {
+            assert_eq!(first_day_exceeding(&LANTERNFISH, &EXAMPLE, 4), Some(0));
+            assert_eq!(first_day_exceeding(&LANTERNFISH, &EXAMPLE, 5), Some(2));
+            assert_eq!(first_day_exceeding(&LANTERNFISH, &EXAMPLE, 5933), Some(80));
+            assert_eq!(first_day_exceeding(&LANTERNFISH, &EXAMPLE, 5934), Some(81));
+            assert_eq!(first_day_exceeding(&LANTERNFISH, &[], 0), None);
+        }
+
+        #[test]
+        fn never_exceeding_test() {
+            // Never reproduces and never dies.
+            let barren = PopulationModel::new(7, 2, 0);
+            assert_eq!(first_day_exceeding(&barren, &EXAMPLE, 5), None);
+            assert_eq!(first_day_exceeding(&barren, &EXAMPLE, 4), Some(0));
+
+            // Dies out.
+            let short_lived = LANTERNFISH.with_lifespan(9);
+            assert_eq!(first_day_exceeding(&short_lived, &[8], 1), None);
+
+            // Reproduces once and dies, so every fish is replaced by one fish.
+            let replacing = LANTERNFISH.with_lifespan(10);
+            assert_eq!(first_day_exceeding(&replacing, &[0, 3, 8], 4), None);
+            assert_eq!(first_day_exceeding(&replacing, &[0, 3, 8], 3), Some(1));
+        }
+
+        #[test]
+        fn growth_rate_test() {
+            assert_eq!(growth_rate(&LANTERNFISH, &[], 0, 10), None);
+            assert_eq!(growth_rate(&LANTERNFISH, &EXAMPLE, 0, 1), Some(1.0));
+
+            // 5 to 26 fishes.
+            let rate = growth_rate(&LANTERNFISH, &EXAMPLE, 0, 18).unwrap();
+            assert!((rate.powi(18) - 26.0 / 5.0).abs() < 1e-9);
+
+            // Approaches the largest root of x^9 = x^2 + 1.
+            let rate = growth_rate(&LANTERNFISH, &EXAMPLE, 200, 256).unwrap();
+            assert!((rate - 1.0910).abs() < 1e-3, "{}", rate);
+        }
+    }
+
+    mod stochastic {
+        use crate::day_06::{
+            laternfish_after_n_days_brute_force, laternfish_after_n_days_stochastic, CycleJitter,
+            PopulationModel, LANTERNFISH,
+        };
+
+        use super::EXAMPLE;
+
+        #[test]
+        fn no_jitter_test() {
+            let mortal = LANTERNFISH.with_lifespan(30);
+
+            for (model, jitter) in [
+                (LANTERNFISH, CycleJitter::None),
+                (mortal, CycleJitter::Uniform(0)),
+                (LANTERNFISH, CycleJitter::Weighted(vec![(0, 1)])),
+            ] {
+                let statistics = laternfish_after_n_days_stochastic(
+                    &model,
+                    &jitter,
+                    &EXAMPLE,
+                    80,
+                    5,
+                    1,
+                    &[0, 50, 100],
+                );
+
+                assert_eq!(statistics.len(), 81);
+                for daily in statistics {
+                    let expected = laternfish_after_n_days_brute_force(&model, &EXAMPLE, daily.day);
+
+                    assert_eq!(daily.mean, expected as f64);
+                    assert_eq!(daily.variance, 0.0);
+                    assert_eq!(daily.percentiles, vec![expected; 3]);
+                }
+            }
+        }
+
+        #[test]
+        fn uniform_jitter_test() {
+            let statistics = laternfish_after_n_days_stochastic(
+                &LANTERNFISH,
+                &CycleJitter::Uniform(2),
+                &EXAMPLE,
+                60,
+                200,
+                2021,
+                &[5, 50, 95],
+            );
+
+            let same_seed = laternfish_after_n_days_stochastic(
+                &LANTERNFISH,
+                &CycleJitter::Uniform(2),
+                &EXAMPLE,
+                60,
+                200,
+                2021,
+                &[5, 50, 95],
+            );
+            assert_eq!(statistics, same_seed);
+
+            let last = statistics.last().unwrap();
+            assert!(last.variance > 0.0);
+            assert!(last.percentiles[0] <= last.percentiles[1]);
+            assert!(last.percentiles[1] <= last.percentiles[2]);
+
+            // The jitter is symmetric, so the population should stay close to
+            // the deterministic one.
+            let expected = laternfish_after_n_days_brute_force(&LANTERNFISH, &EXAMPLE, 60) as f64;
+            assert!(
+                (last.mean - expected).abs() / expected < 0.1,
+                "{} vs {}",
+                last.mean,
+                expected
+            );
+        }
+
+        #[test]
+        fn weighted_jitter_test() {
+            // Cycles are always one day shorter, so fishes reproduce faster.
+            let statistics = laternfish_after_n_days_stochastic(
+                &LANTERNFISH,
+                &CycleJitter::Weighted(vec![(-1, 1), (3, 0)]),
+                &EXAMPLE,
+                40,
+                3,
+                7,
+                &[50],
+            );
+
+            let faster = PopulationModel::new(6, 2, 1);
+            for daily in statistics {
+                assert_eq!(daily.variance, 0.0);
+                assert_eq!(
+                    daily.percentiles[0],
+                    laternfish_after_n_days_brute_force(&faster, &EXAMPLE, daily.day),
+                );
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "Uniform spread should not be negative")]
+        fn negative_spread_test() {
+            laternfish_after_n_days_stochastic(
+                &LANTERNFISH,
+                &CycleJitter::Uniform(-1),
+                &EXAMPLE,
+                10,
+                1,
+                7,
+                &[],
+            );
+        }
+
+        #[test]
+        #[should_panic(expected = "Weighted jitter needs at least one positive weight")]
+        fn zero_weights_test() {
+            laternfish_after_n_days_stochastic(
+                &LANTERNFISH,
+                &CycleJitter::Weighted(vec![(0, 0), (1, 0)]),
+                &EXAMPLE,
+                10,
+                1,
+                7,
+                &[],
+            );
         }
     }
 }
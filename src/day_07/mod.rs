@@ -1,34 +1,295 @@
 /// Minimize `abs(a1 - x) + abs(a2 - x) + ...`.
 #[allow(dead_code)]
 fn fuel_to_align(positions: &Vec<i64>) -> i64 {
-    fuel_to_align_using_fuel_calculation(|steps| steps, positions)
+    fuel_to_align_using_fuel_calculation(|steps| steps, Convexity::Convex, positions)
 }
 
 /// Minimize `calc(abs(a1 - x) + calc(a2 - x) + ...`
 /// where `calc(n)` is `1 + 2 + ... + n`.
 #[allow(dead_code)]
 fn fuel_to_align_non_constant_fuel(positions: &Vec<i64>) -> i64 {
-    fuel_to_align_using_fuel_calculation(|steps| (steps * (steps + 1)) / 2, positions)
+    fuel_to_align_using_fuel_calculation(
+        |steps| (steps * (steps + 1)) / 2,
+        Convexity::Convex,
+        positions,
+    )
+}
+
+/// What is known about the shape of a fuel calculation.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Convexity {
+    /// Every position between the outermost crabs is tried.
+    Unknown,
+    /// The fuel calculation is convex and non-decreasing for steps >= 0. The
+    /// total fuel is then convex in the position (as long as multipliers are
+    /// not negative), so the best position can be binary searched.
+    Convex,
 }
 
 fn fuel_to_align_using_fuel_calculation(
-    fuel_calculation: fn(i64) -> i64,
-    positions: &Vec<i64>,
+    fuel_calculation: impl Fn(i64) -> i64,
+    convexity: Convexity,
+    positions: &[i64],
+) -> i64 {
+    let crabs = positions
+        .iter()
+        .map(|position| (*position, 1))
+        .collect::<Vec<_>>();
+
+    fuel_to_align_weighted(fuel_calculation, convexity, &crabs)
+}
+
+/// Minimize `m1 * calc(abs(a1 - x)) + m2 * calc(abs(a2 - x)) + ...` for crabs
+/// given as `(a, m)` pairs of position and fuel multiplier.
+fn fuel_to_align_weighted(
+    fuel_calculation: impl Fn(i64) -> i64,
+    convexity: Convexity,
+    crabs: &[(i64, i64)],
 ) -> i64 {
+    alignment(fuel_calculation, convexity, crabs, false).fuel
+}
+
+/// The best positions to align to.
+#[derive(Debug, Clone, PartialEq)]
+struct AlignmentResult {
+    /// Every position using the least fuel, in increasing order.
+    positions: Vec<i64>,
+    fuel: i64,
+    /// `(position, fuel)` for every position between the outermost crabs.
+    cost_curve: Option<Vec<(i64, i64)>>,
+}
+
+impl AlignmentResult {
+    /// The cost curve as CSV with a header row, if it was computed.
+    #[allow(dead_code)]
+    fn cost_curve_csv(&self) -> Option<String> {
+        self.cost_curve.as_ref().map(|curve| {
+            curve.iter().fold(
+                String::from("position,fuel\n"),
+                |mut acc, (position, fuel)| {
+                    acc.push_str(&format!("{},{}\n", position, fuel));
+                    acc
+                },
+            )
+        })
+    }
+}
+
+/// Same as [`fuel_to_align_weighted`], but also returns every best position
+/// and, if `with_cost_curve` is set, the fuel for every position.
+fn alignment(
+    fuel_calculation: impl Fn(i64) -> i64,
+    convexity: Convexity,
+    crabs: &[(i64, i64)],
+    with_cost_curve: bool,
+) -> AlignmentResult {
+    if crabs.is_empty() {
+        return AlignmentResult {
+            positions: Vec::new(),
+            fuel: 0,
+            cost_curve: with_cost_curve.then(Vec::new),
+        };
+    }
+
+    let total_fuel = |guess: i64| {
+        crabs.iter().fold(0, |acc, (x, multiplier)| {
+            let steps = (x - guess).abs();
+            acc + multiplier * fuel_calculation(steps)
+        })
+    };
+
+    let mut best_position_lower = crabs.iter().map(|(x, _)| *x).min().unwrap();
+    let mut best_position_upper = crabs.iter().map(|(x, _)| *x).max().unwrap();
+
+    let cost_curve = (with_cost_curve || convexity == Convexity::Unknown).then(|| {
+        (best_position_lower..best_position_upper + 1)
+            .map(|guess| (guess, total_fuel(guess)))
+            .collect::<Vec<_>>()
+    });
+
+    let (positions, fuel) = match convexity {
+        Convexity::Convex => {
+            let range_upper = best_position_upper;
+
+            // Find the first position where the fuel stops decreasing.
+            while best_position_lower < best_position_upper {
+                let mid = best_position_lower + (best_position_upper - best_position_lower) / 2;
+                if total_fuel(mid) <= total_fuel(mid + 1) {
+                    best_position_upper = mid;
+                } else {
+                    best_position_lower = mid + 1;
+                }
+            }
+            let fuel = total_fuel(best_position_lower);
+
+            // The best positions of a convex function are next to each other.
+            let positions = (best_position_lower..range_upper + 1)
+                .take_while(|guess| total_fuel(*guess) == fuel)
+                .collect();
+            (positions, fuel)
+        }
+        Convexity::Unknown => {
+            let curve = cost_curve.as_ref().unwrap();
+            let fuel = curve.iter().map(|(_, fuel)| *fuel).min().unwrap();
+            let positions = curve
+                .iter()
+                .filter(|(_, guess_fuel)| *guess_fuel == fuel)
+                .map(|(guess, _)| *guess)
+                .collect();
+            (positions, fuel)
+        }
+    };
+
+    AlignmentResult {
+        positions,
+        fuel,
+        cost_curve: if with_cost_curve { cost_curve } else { None },
+    }
+}
+
+/// How much fuel a crab uses to move in `D` dimensions.
+#[allow(dead_code)]
+enum MoveCost<'a, const D: usize> {
+    /// The fuel is `calc(abs(dx)) + calc(abs(dy)) + ...`, e.g. Manhattan
+    /// distance for `calc(n) = n`. Each axis is then aligned on its own.
+    PerAxis(&'a dyn Fn(i64) -> i64, Convexity),
+    /// Any fuel calculation on the whole displacement, e.g. Euclidean distance.
+    Combined(&'a dyn Fn([i64; D]) -> i64),
+}
+
+/// Where crabs in `D` dimensions meet.
+#[derive(Debug, Clone, PartialEq)]
+struct MeetingPoint<const D: usize> {
+    position: [i64; D],
+    fuel: i64,
+}
+
+/// Finds where crabs at `points` should meet to use the least fuel.
+///
+/// [`MoveCost::PerAxis`] costs give an exact answer. [`MoveCost::Combined`]
+/// costs fall back to a pattern search from the per-axis medians: move to any
+/// neighbour (including diagonals) `step` away that uses less fuel, and halve
+/// `step` when there are none. This finds a local minimum, which is the best
+/// position for most convex costs.
+#[allow(dead_code)]
+fn align_points<const D: usize>(points: &[[i64; D]], cost: &MoveCost<D>) -> MeetingPoint<D> {
+    if points.is_empty() {
+        return MeetingPoint {
+            position: [0; D],
+            fuel: 0,
+        };
+    }
+
+    let axis = |d: usize| points.iter().map(|point| (point[d], 1)).collect::<Vec<_>>();
+
+    match cost {
+        MoveCost::PerAxis(fuel_calculation, convexity) => {
+            let alignments = (0..D)
+                .map(|d| alignment(fuel_calculation, *convexity, &axis(d), false))
+                .collect::<Vec<_>>();
+
+            MeetingPoint {
+                position: std::array::from_fn(|d| alignments[d].positions[0]),
+                fuel: alignments.iter().map(|result| result.fuel).sum(),
+            }
+        }
+        MoveCost::Combined(fuel_calculation) => {
+            let total_fuel = |guess: &[i64; D]| {
+                points
+                    .iter()
+                    .map(|point| fuel_calculation(std::array::from_fn(|d| point[d] - guess[d])))
+                    .sum::<i64>()
+            };
+
+            let mut position: [i64; D] = std::array::from_fn(|d| {
+                let mut coordinates = points.iter().map(|point| point[d]).collect::<Vec<_>>();
+                coordinates.sort_unstable();
+                coordinates[coordinates.len() / 2]
+            });
+            let mut fuel = total_fuel(&position);
+
+            // Every combination of -1, 0 and 1 for each axis, except all zeros.
+            let directions = (0..3usize.pow(D as u32))
+                .map(|i| {
+                    std::array::from_fn::<i64, D, _>(|d| (i / 3usize.pow(d as u32) % 3) as i64 - 1)
+                })
+                .filter(|direction| direction.iter().any(|x| *x != 0))
+                .collect::<Vec<_>>();
+
+            let mut step = (0..D)
+                .map(|d| {
+                    let coordinates = points.iter().map(|point| point[d]);
+                    coordinates.clone().max().unwrap() - coordinates.min().unwrap()
+                })
+                .max()
+                .unwrap()
+                .max(1);
+
+            while step > 0 {
+                let better = directions
+                    .iter()
+                    .map(|direction| std::array::from_fn(|d| position[d] + direction[d] * step))
+                    .map(|guess| (total_fuel(&guess), guess))
+                    .filter(|(guess_fuel, _)| *guess_fuel < fuel)
+                    .min();
+
+                match better {
+                    Some((better_fuel, better_position)) => {
+                        fuel = better_fuel;
+                        position = better_position;
+                    }
+                    None => step /= 2,
+                }
+            }
+
+            MeetingPoint { position, fuel }
+        }
+    }
+}
+
+/// Same as [`fuel_to_align`], in O(n log n).
+///
+/// `abs(a1 - x) + abs(a2 - x) + ...` is minimised by any median of the
+/// positions.
+#[allow(dead_code)]
+fn fuel_to_align_median(positions: &[i64]) -> i64 {
     if positions.is_empty() {
         return 0;
     }
 
-    let best_position_lower = *positions.iter().min().unwrap();
-    let best_position_upper = *positions.iter().max().unwrap();
+    let mut sorted = positions.to_vec();
+    sorted.sort_unstable();
+    let median = sorted[sorted.len() / 2];
+
+    positions.iter().map(|x| (x - median).abs()).sum()
+}
 
-    (best_position_lower..best_position_upper + 1)
-        .into_iter()
+/// Same as [`fuel_to_align_non_constant_fuel`], in O(n).
+///
+/// `calc(n)` is `(n^2 + n) / 2`, so the sum is minimised within 0.5 of the
+/// mean of the positions. As the sum is convex, the best whole position is one
+/// of those next to the mean.
+#[allow(dead_code)]
+fn fuel_to_align_non_constant_fuel_mean(positions: &[i64]) -> i64 {
+    if positions.is_empty() {
+        return 0;
+    }
+
+    let mean = positions
+        .iter()
+        .sum::<i64>()
+        .div_euclid(positions.len() as i64);
+
+    (mean - 1..=mean + 2)
         .map(|guess| {
-            positions.iter().fold(0, |acc, x| {
-                let steps = (x - guess).abs();
-                acc + fuel_calculation(steps)
-            })
+            positions
+                .iter()
+                .map(|x| {
+                    let steps = (x - guess).abs();
+                    (steps * (steps + 1)) / 2
+                })
+                .sum::<i64>()
         })
         .min()
         .unwrap()
@@ -50,6 +311,14 @@ mod tests {
 
     const EXAMPLE: [i64; 10] = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
 
+    fn linear(steps: i64) -> i64 {
+        steps
+    }
+
+    fn triangular(steps: i64) -> i64 {
+        (steps * (steps + 1)) / 2
+    }
+
     mod part_1 {
         use super::super::fuel_to_align;
         use super::{parse_input, EXAMPLE};
@@ -94,4 +363,382 @@ mod tests {
             assert_eq!(fuel_to_align_non_constant_fuel(&input), 101079875,);
         }
     }
+
+    mod closed_form {
+        use crate::common::rng::Rng;
+
+        use super::super::{
+            fuel_to_align_median, fuel_to_align_non_constant_fuel_mean,
+            fuel_to_align_using_fuel_calculation, Convexity,
+        };
+        use super::{parse_input, EXAMPLE};
+
+        /// Tries every position between the outermost crabs.
+        fn brute_force(calculation: impl Fn(i64) -> i64, positions: &[i64]) -> i64 {
+            fuel_to_align_using_fuel_calculation(calculation, Convexity::Unknown, positions)
+        }
+
+        #[test]
+        fn empty_test() {
+            assert_eq!(fuel_to_align_median(&[]), 0);
+            assert_eq!(fuel_to_align_non_constant_fuel_mean(&[]), 0);
+        }
+
+        #[test]
+        fn example_test() {
+            assert_eq!(fuel_to_align_median(&EXAMPLE), 37);
+            assert_eq!(fuel_to_align_non_constant_fuel_mean(&EXAMPLE), 168);
+        }
+
+        #[test]
+        fn random_test() {
+            let mut rng = Rng::new(7);
+
+            for _ in 0..200 {
+                let len = rng.below(20) as usize + 1;
+                let positions = (0..len)
+                    .map(|_| rng.below(100) as i64 - 50)
+                    .collect::<Vec<i64>>();
+
+                assert_eq!(
+                    fuel_to_align_median(&positions),
+                    brute_force(|steps| steps, &positions),
+                    "failed for {:?}",
+                    positions
+                );
+                assert_eq!(
+                    fuel_to_align_non_constant_fuel_mean(&positions),
+                    brute_force(|steps| (steps * (steps + 1)) / 2, &positions),
+                    "failed for {:?}",
+                    positions
+                );
+            }
+        }
+
+        #[test]
+        fn solution() {
+            let input = parse_input();
+            assert_eq!(fuel_to_align_median(&input), 351901);
+            assert_eq!(fuel_to_align_non_constant_fuel_mean(&input), 101079875);
+        }
+    }
+
+    mod cost_functions {
+        use crate::common::rng::Rng;
+
+        use super::super::{
+            fuel_to_align_using_fuel_calculation, fuel_to_align_weighted, Convexity,
+        };
+        use super::{parse_input, EXAMPLE};
+
+        fn random_positions(rng: &mut Rng) -> Vec<i64> {
+            let len = rng.below(20) as usize + 1;
+            (0..len).map(|_| rng.below(100) as i64 - 50).collect()
+        }
+
+        #[test]
+        fn quadratic_test() {
+            let mut rng = Rng::new(33);
+
+            for _ in 0..100 {
+                let positions = random_positions(&mut rng);
+                let factor = rng.below(5) as i64 + 1;
+                let quadratic = |steps: i64| factor * steps * steps;
+
+                assert_eq!(
+                    fuel_to_align_using_fuel_calculation(quadratic, Convexity::Convex, &positions),
+                    fuel_to_align_using_fuel_calculation(quadratic, Convexity::Unknown, &positions),
+                    "failed for {:?}",
+                    positions
+                );
+            }
+        }
+
+        #[test]
+        fn capped_test() {
+            // Capped costs aren't convex, so the best position is between the
+            // two clusters rather than at either of them.
+            let cap = 10;
+            let capped = |steps: i64| steps.min(cap);
+            let positions = [0, 0, 0, 100, 100, 100, 100];
+
+            assert_eq!(
+                fuel_to_align_using_fuel_calculation(capped, Convexity::Unknown, &positions),
+                30
+            );
+        }
+
+        #[test]
+        fn multipliers_test() {
+            let mut rng = Rng::new(34);
+
+            for _ in 0..100 {
+                let crabs = random_positions(&mut rng)
+                    .into_iter()
+                    .map(|position| (position, rng.below(4) as i64))
+                    .collect::<Vec<_>>();
+
+                for calculation in [|steps: i64| steps, |steps: i64| (steps * (steps + 1)) / 2] {
+                    assert_eq!(
+                        fuel_to_align_weighted(calculation, Convexity::Convex, &crabs),
+                        fuel_to_align_weighted(calculation, Convexity::Unknown, &crabs),
+                        "failed for {:?}",
+                        crabs
+                    );
+                }
+            }
+
+            // A heavy crab pulls everyone to itself.
+            assert_eq!(
+                fuel_to_align_weighted(|steps| steps, Convexity::Convex, &[(0, 1), (10, 5)]),
+                10
+            );
+        }
+
+        #[test]
+        fn example_test() {
+            for calculation in [|steps: i64| steps, |steps: i64| (steps * (steps + 1)) / 2] {
+                assert_eq!(
+                    fuel_to_align_using_fuel_calculation(calculation, Convexity::Convex, &EXAMPLE),
+                    fuel_to_align_using_fuel_calculation(calculation, Convexity::Unknown, &EXAMPLE),
+                );
+            }
+        }
+
+        #[test]
+        fn solution() {
+            let input = parse_input();
+            assert_eq!(
+                fuel_to_align_using_fuel_calculation(|steps| steps, Convexity::Unknown, &input),
+                351901
+            );
+        }
+    }
+
+    mod alignment_result {
+        use super::super::{alignment, AlignmentResult, Convexity};
+        use super::{linear, parse_input, triangular, EXAMPLE};
+
+        fn crabs(positions: &[i64]) -> Vec<(i64, i64)> {
+            positions.iter().map(|position| (*position, 1)).collect()
+        }
+
+        #[test]
+        fn empty_test() {
+            assert_eq!(
+                alignment(linear, Convexity::Convex, &[], true),
+                AlignmentResult {
+                    positions: Vec::new(),
+                    fuel: 0,
+                    cost_curve: Some(Vec::new()),
+                }
+            );
+        }
+
+        #[test]
+        fn example_test() {
+            let crabs = crabs(&EXAMPLE);
+
+            for convexity in [Convexity::Convex, Convexity::Unknown] {
+                let result = alignment(linear, convexity, &crabs, false);
+                assert_eq!(result.positions, vec![2]);
+                assert_eq!(result.fuel, 37);
+                assert_eq!(result.cost_curve, None);
+
+                let result = alignment(triangular, convexity, &crabs, false);
+                assert_eq!(result.positions, vec![5]);
+                assert_eq!(result.fuel, 168);
+            }
+        }
+
+        #[test]
+        fn ties_test() {
+            // Every position between an even number of crabs is a median.
+            for convexity in [Convexity::Convex, Convexity::Unknown] {
+                let result = alignment(linear, convexity, &crabs(&[0, 10, 3, 6]), false);
+                assert_eq!(result.positions, vec![3, 4, 5, 6]);
+                assert_eq!(result.fuel, 13);
+
+                let result = alignment(triangular, convexity, &crabs(&[0, 1]), false);
+                assert_eq!(result.positions, vec![0, 1]);
+                assert_eq!(result.fuel, 1);
+            }
+
+            // Not next to each other when the fuel isn't convex.
+            let capped = |steps: i64| steps.min(2);
+            let result = alignment(capped, Convexity::Unknown, &crabs(&[0, 5, 10]), false);
+            assert_eq!(result.positions, vec![0, 5, 10]);
+            assert_eq!(result.fuel, 4);
+        }
+
+        #[test]
+        fn cost_curve_test() {
+            let result = alignment(triangular, Convexity::Convex, &crabs(&[0, 1, 3]), true);
+
+            assert_eq!(
+                result.cost_curve,
+                Some(vec![(0, 7), (1, 4), (2, 5), (3, 9)])
+            );
+            assert_eq!(
+                result.cost_curve_csv().unwrap(),
+                "position,fuel\n0,7\n1,4\n2,5\n3,9\n"
+            );
+            assert_eq!(
+                alignment(linear, Convexity::Convex, &crabs(&[0]), false).cost_curve_csv(),
+                None
+            );
+        }
+
+        #[test]
+        fn solution() {
+            let crabs = crabs(&parse_input());
+
+            let result = alignment(linear, Convexity::Convex, &crabs, true);
+            assert_eq!(result.fuel, 351901);
+
+            let curve = result.cost_curve.unwrap();
+            for position in result.positions {
+                assert!(curve.contains(&(position, 351901)));
+            }
+            assert!(curve.iter().all(|(_, fuel)| *fuel >= 351901));
+        }
+    }
+
+    mod multi_dimensional {
+        use crate::common::rng::Rng;
+
+        use super::super::{align_points, fuel_to_align, Convexity, MeetingPoint, MoveCost};
+        use super::{linear, triangular, EXAMPLE};
+
+        /// Euclidean distance in thousandths, rounded.
+        fn euclidean<const D: usize>(displacement: [i64; D]) -> i64 {
+            let squared = displacement.iter().map(|x| x * x).sum::<i64>() as f64;
+            (squared.sqrt() * 1000.0).round() as i64
+        }
+
+        fn manhattan<const D: usize>(displacement: [i64; D]) -> i64 {
+            displacement.iter().map(|x| x.abs()).sum()
+        }
+
+        /// Tries every position in the bounding box of `points`.
+        fn brute_force<const D: usize>(
+            points: &[[i64; D]],
+            fuel_calculation: impl Fn([i64; D]) -> i64,
+        ) -> i64 {
+            let lower: [i64; D] =
+                std::array::from_fn(|d| points.iter().map(|point| point[d]).min().unwrap());
+            let upper: [i64; D] =
+                std::array::from_fn(|d| points.iter().map(|point| point[d]).max().unwrap());
+
+            let mut guesses = vec![lower];
+            for d in 0..D {
+                guesses = guesses
+                    .into_iter()
+                    .flat_map(|guess| {
+                        (lower[d]..=upper[d]).map(move |x| {
+                            let mut next = guess;
+                            next[d] = x;
+                            next
+                        })
+                    })
+                    .collect();
+            }
+
+            guesses
+                .into_iter()
+                .map(|guess| {
+                    points
+                        .iter()
+                        .map(|point| fuel_calculation(std::array::from_fn(|d| point[d] - guess[d])))
+                        .sum::<i64>()
+                })
+                .min()
+                .unwrap()
+        }
+
+        fn random_points<const D: usize>(rng: &mut Rng) -> Vec<[i64; D]> {
+            let len = rng.below(8) as usize + 1;
+            (0..len)
+                .map(|_| std::array::from_fn(|_| rng.below(12) as i64 - 6))
+                .collect()
+        }
+
+        #[test]
+        fn empty_test() {
+            assert_eq!(
+                align_points::<2>(&[], &MoveCost::PerAxis(&linear, Convexity::Convex)),
+                MeetingPoint {
+                    position: [0, 0],
+                    fuel: 0
+                }
+            );
+        }
+
+        #[test]
+        fn one_dimension_test() {
+            let points = EXAMPLE.map(|x| [x]);
+
+            let result = align_points(&points, &MoveCost::PerAxis(&linear, Convexity::Convex));
+            assert_eq!(result.fuel, fuel_to_align(&EXAMPLE.to_vec()));
+            assert_eq!(result.position, [2]);
+        }
+
+        #[test]
+        fn per_axis_test() {
+            let mut rng = Rng::new(35);
+
+            for _ in 0..50 {
+                let points = random_points::<2>(&mut rng);
+                let result = align_points(&points, &MoveCost::PerAxis(&linear, Convexity::Convex));
+                assert_eq!(result.fuel, brute_force(&points, manhattan), "{:?}", points);
+
+                let points = random_points::<3>(&mut rng);
+                let result =
+                    align_points(&points, &MoveCost::PerAxis(&triangular, Convexity::Convex));
+                let per_axis_triangular =
+                    |displacement: [i64; 3]| displacement.map(|x| triangular(x.abs())).iter().sum();
+                assert_eq!(
+                    result.fuel,
+                    brute_force(&points, per_axis_triangular),
+                    "{:?}",
+                    points
+                );
+            }
+        }
+
+        #[test]
+        fn combined_test() {
+            let mut rng = Rng::new(36);
+
+            for _ in 0..50 {
+                let points = random_points::<2>(&mut rng);
+
+                // Manhattan distance is separable, so the local search should
+                // find the best position too.
+                let result = align_points(&points, &MoveCost::Combined(&manhattan));
+                assert_eq!(result.fuel, brute_force(&points, manhattan), "{:?}", points);
+
+                let result = align_points(&points, &MoveCost::Combined(&euclidean));
+                let expected = brute_force(&points, euclidean);
+                assert!(result.fuel >= expected);
+                assert!(
+                    result.fuel as f64 <= expected as f64 * 1.01,
+                    "{:?}: {} vs {}",
+                    points,
+                    result.fuel,
+                    expected
+                );
+            }
+        }
+
+        #[test]
+        fn euclidean_test() {
+            // The geometric median of a square's corners is its centre.
+            let points = [[0, 0], [0, 10], [10, 0], [10, 10]];
+            let result = align_points(&points, &MoveCost::Combined(&euclidean));
+
+            assert_eq!(result.position, [5, 5]);
+            assert_eq!(result.fuel, 4 * 7071);
+        }
+    }
 }
@@ -1,9 +1,10 @@
-use std::collections::{HashMap, HashSet};
+use std::{collections::BTreeSet, ops::ControlFlow};
 
-// This is bad and I feel bad. I didn't read the question properly to understand
-// that the first 10 signal patterns are combinations from 0 - 9. This
-// implementation does a backtracking search to find the solution instead, which
-// is slow. (Maybe dynamic programming could make this faster)
+use crate::common::rng::Rng;
+
+// The first 10 signal patterns are the digits 0 - 9 in some order, so which
+// pattern is which digit can be deduced from how the patterns overlap, without
+// working out the wiring itself.
 
 #[allow(dead_code)]
 fn times_1_4_7_8_appear(input: &Vec<(Vec<String>, Vec<String>)>) -> usize {
@@ -17,170 +18,558 @@ fn times_1_4_7_8_appear(input: &Vec<(Vec<String>, Vec<String>)>) -> usize {
 
 #[allow(dead_code)]
 fn all_output_values_sum(input: &Vec<(Vec<String>, Vec<String>)>) -> u64 {
-    let mut result = 0;
-
-    for (signal_pattern, outputs) in input {
-        let mut current_result = 0;
-
-        let mut solution: HashMap<char, HashSet<u8>> =
-            HashMap::from_iter(('a'..='g').into_iter().map(|char| {
-                let initial_possibilities = HashSet::from_iter(1..=8);
-                (char, initial_possibilities)
-            }));
-        let patterns = signal_pattern.into_iter().chain(outputs.into_iter());
-
-        // This just prunes the tree and is not strictly needed for correctness.
-        // It affects performance greatly though.
-        for pattern in patterns {
-            match pattern.len() {
-                // Signal for 1.
-                2 => {
-                    for char in pattern.chars() {
-                        let char_possibilities = solution.get_mut(&char).unwrap();
-                        keep(char_possibilities, vec![2, 3]);
-                    }
-                }
-                // Signal for 4.
-                4 => {
-                    for char in pattern.chars() {
-                        let char_possibilities = solution.get_mut(&char).unwrap();
-                        keep(char_possibilities, vec![6, 2, 7, 3]);
-                    }
-                }
-                // Signal for 7.
-                3 => {
-                    for char in pattern.chars() {
-                        let char_possibilities = solution.get_mut(&char).unwrap();
-                        keep(char_possibilities, vec![1, 2, 3]);
-                    }
-                }
-                // Signal for 8.
-                // TODO: Might as well do nothing here?
-                7 => {
-                    for char in pattern.chars() {
-                        let char_possibilities = solution.get_mut(&char).unwrap();
-                        keep(char_possibilities, vec![1, 2, 3, 4, 5, 6, 7, 8]);
-                    }
-                }
-                _ => (),
-            }
-        }
+    input
+        .iter()
+        .map(|(signal_patterns, outputs)| decode_entry(signal_patterns, outputs))
+        .sum()
+}
 
-        let valid_check = |solution| {
-            for signal in signal_pattern.into_iter().chain(outputs.into_iter()) {
-                if signal_to_number(&solution, signal).is_none() {
-                    return false;
-                }
-            }
-            return true;
-        };
+/// A set of wires or segments, with bit 0 for `a` up to bit 6 for `g`.
+type Pattern = u8;
 
-        if let Some(solution) = backtrack(&valid_check, solution.clone()) {
-            for signal in outputs {
-                current_result *= 10;
-                current_result += signal_to_number(&solution, signal).unwrap() as u64;
-            }
-        } else {
-            panic!("No solution found");
-        };
+fn to_pattern(signal: &str) -> Pattern {
+    signal.chars().fold(0, |acc, char| {
+        assert!(('a'..='g').contains(&char), "Unknown wire {}", char);
+        acc | 1 << (char as u8 - b'a')
+    })
+}
 
-        result += current_result;
+/// Whether every wire in `subset` is also in `superset`.
+fn contains(superset: Pattern, subset: Pattern) -> bool {
+    superset & subset == subset
+}
+
+/// Works out which of the 10 unique `signal_patterns` is which digit, returning
+/// a table from pattern to digit.
+///
+/// 1, 4, 7 and 8 are the only digits with 2, 4, 3 and 7 segments.
+///
+/// Of the digits with 6 segments, only 9 contains 4, and of the rest only 0
+/// contains 1. Of the digits with 5 segments, only 3 contains 1, and of the
+/// rest only 5 is contained by 6.
+fn deduce_digits(signal_patterns: &[String]) -> [Option<u8>; 128] {
+    let patterns = signal_patterns
+        .iter()
+        .map(|signal| to_pattern(signal))
+        .collect::<Vec<Pattern>>();
+
+    let find = |len: u32, is_digit: &dyn Fn(Pattern) -> bool| {
+        *patterns
+            .iter()
+            .find(|pattern| pattern.count_ones() == len && is_digit(**pattern))
+            .expect("No solution found")
+    };
+
+    let one = find(2, &|_| true);
+    let four = find(4, &|_| true);
+    let seven = find(3, &|_| true);
+    let eight = find(7, &|_| true);
+    let nine = find(6, &|pattern| contains(pattern, four));
+    let zero = find(6, &|pattern| pattern != nine && contains(pattern, one));
+    let six = find(6, &|pattern| pattern != nine && pattern != zero);
+    let three = find(5, &|pattern| contains(pattern, one));
+    let five = find(5, &|pattern| pattern != three && contains(six, pattern));
+    let two = find(5, &|pattern| pattern != three && pattern != five);
+
+    let mut digits = [None; 128];
+    for (digit, pattern) in [zero, one, two, three, four, five, six, seven, eight, nine]
+        .into_iter()
+        .enumerate()
+    {
+        digits[pattern as usize] = Some(digit as u8);
     }
-    result
+    digits
 }
 
-fn keep(possibilities: &mut HashSet<u8>, to_keep: Vec<u8>) {
-    possibilities.retain(|x| to_keep.contains(x));
+fn decode_entry(signal_patterns: &[String], outputs: &[String]) -> u64 {
+    let digits = deduce_digits(signal_patterns);
 
-    assert!(possibilities.len() > 0);
+    outputs.iter().fold(0, |acc, output| {
+        let digit = digits[to_pattern(output) as usize].expect("Output should be a digit");
+        acc * 10 + digit as u64
+    })
 }
 
-//  1111
-// 6    2
-// 6    2
-//  7777
-// 5    3
-// 5    3
-//  4444
-fn positions_to_number(positions: &HashSet<u8>) -> Option<u8> {
-    if positions == &HashSet::from_iter(vec![1, 2, 3, 4, 5, 6]) {
-        Some(0)
-    } else if positions == &HashSet::from_iter(vec![2, 3]) {
-        Some(1)
-    } else if positions == &HashSet::from_iter(vec![1, 2, 7, 5, 4]) {
-        Some(2)
-    } else if positions == &HashSet::from_iter(vec![1, 2, 7, 3, 4]) {
-        Some(3)
-    } else if positions == &HashSet::from_iter(vec![6, 7, 2, 3]) {
-        Some(4)
-    } else if positions == &HashSet::from_iter(vec![1, 6, 7, 3, 4]) {
-        Some(5)
-    } else if positions == &HashSet::from_iter(vec![1, 6, 5, 4, 3, 7]) {
-        Some(6)
-    } else if positions == &HashSet::from_iter(vec![1, 2, 3]) {
-        Some(7)
-    } else if positions == &HashSet::from_iter(vec![1, 6, 7, 3, 4, 5, 2]) {
-        Some(8)
-    } else if positions == &HashSet::from_iter(vec![1, 6, 7, 2, 3, 4]) {
-        Some(9)
-    } else {
-        None
-    }
+/// The segments of a display and the glyphs it can show.
+#[derive(Debug, Clone, PartialEq)]
+struct DisplayLayout {
+    /// Segment names, where segment `i` is bit `i` of a glyph.
+    segments: Vec<&'static str>,
+    /// Each glyph with the segments that are lit to show it.
+    glyphs: Vec<(char, u32)>,
 }
 
-fn backtrack(
-    valid_check: &dyn Fn(HashMap<char, HashSet<u8>>) -> bool,
-    possibilities: HashMap<char, HashSet<u8>>,
-) -> Option<HashMap<char, HashSet<u8>>> {
-    let maybe_candidate = possibilities
-        .iter()
-        // K is a definite solution and is not a candidate.
-        .filter(|(_, v)| v.len() != 1)
-        .min_by_key(|(_, p)| p.len());
-
-    if maybe_candidate.is_none() {
-        if valid_check(possibilities.clone()) {
-            return Some(possibilities);
-        } else {
-            return None;
+impl DisplayLayout {
+    fn new(segments: Vec<&'static str>, glyphs: Vec<(char, u32)>) -> Self {
+        assert!(segments.len() <= 26, "Wires only go from a to z");
+        assert!(glyphs.len() <= 64, "There should be at most 64 glyphs");
+
+        for (i, (glyph, lit)) in glyphs.iter().enumerate() {
+            assert!(
+                *lit < 1 << segments.len(),
+                "Glyph {} uses unknown segments",
+                glyph
+            );
+            assert!(
+                glyphs[..i].iter().all(|(_, other)| other != lit),
+                "Glyph {} looks the same as another glyph",
+                glyph
+            );
         }
+
+        Self { segments, glyphs }
+    }
+
+    /// Builds a glyph table from the names of the segments lit for each glyph.
+    fn from_segment_names(segments: Vec<&'static str>, glyphs: &[(char, &[&str])]) -> Self {
+        let glyphs = glyphs
+            .iter()
+            .map(|(glyph, names)| {
+                let lit = names.iter().fold(0, |acc, name| {
+                    let segment = segments
+                        .iter()
+                        .position(|segment| segment == name)
+                        .unwrap_or_else(|| panic!("Unknown segment {}", name));
+                    acc | 1 << segment
+                });
+                (*glyph, lit)
+            })
+            .collect();
+
+        Self::new(segments, glyphs)
+    }
+
+    //  aaaa
+    // b    c
+    // b    c
+    //  dddd
+    // e    f
+    // e    f
+    //  gggg
+    #[allow(dead_code)]
+    fn seven_segment() -> Self {
+        Self::from_segment_names(
+            vec!["a", "b", "c", "d", "e", "f", "g"],
+            &[
+                ('0', &["a", "b", "c", "e", "f", "g"][..]),
+                ('1', &["c", "f"]),
+                ('2', &["a", "c", "d", "e", "g"]),
+                ('3', &["a", "c", "d", "f", "g"]),
+                ('4', &["b", "c", "d", "f"]),
+                ('5', &["a", "b", "d", "f", "g"]),
+                ('6', &["a", "b", "d", "e", "f", "g"]),
+                ('7', &["a", "c", "f"]),
+                ('8', &["a", "b", "c", "d", "e", "f", "g"]),
+                ('9', &["a", "b", "c", "d", "f", "g"]),
+            ],
+        )
+    }
+
+    //  aaaaaaa
+    // f h j k b
+    // f  hjk  b
+    //  g1   g2
+    // e  lmn  c
+    // e l m n c
+    //  ddddddd
+    #[allow(dead_code)]
+    fn fourteen_segment() -> Self {
+        Self::new(
+            vec![
+                "a", "b", "c", "d", "e", "f", "g1", "g2", "h", "j", "k", "l", "m", "n",
+            ],
+            FOURTEEN_SEGMENT_GLYPHS.to_vec(),
+        )
     }
-    let (candidate, guesses) = maybe_candidate.unwrap();
 
-    for guess in guesses {
-        let mut next_possibilities = possibilities.clone();
+    // Same as the 14 segment display, with the top and bottom segments split
+    // into halves that are both lit whenever the 14 segment one is.
+    //
+    //  a1  a2
+    // f h j k b
+    // f  hjk  b
+    //  g1   g2
+    // e  lmn  c
+    // e l m n c
+    //  d1  d2
+    #[allow(dead_code)]
+    fn sixteen_segment() -> Self {
+        let glyphs = FOURTEEN_SEGMENT_GLYPHS
+            .iter()
+            .map(|(glyph, lit)| {
+                let a = lit & 1;
+                let d = lit >> 3 & 1;
+                let b_c = lit >> 1 & 0b11;
+                let e_to_n = lit >> 4;
+                (
+                    *glyph,
+                    a | a << 1 | b_c << 2 | d << 4 | d << 5 | e_to_n << 6,
+                )
+            })
+            .collect();
+
+        Self::new(
+            vec![
+                "a1", "a2", "b", "c", "d1", "d2", "e", "f", "g1", "g2", "h", "j", "k", "l", "m",
+                "n",
+            ],
+            glyphs,
+        )
+    }
 
-        *next_possibilities.get_mut(candidate).unwrap() = HashSet::from_iter(vec![*guess]);
+    /// The wires in `signal`, with bit 0 for `a`, bit 1 for `b` and so on.
+    fn to_wires(&self, signal: &str) -> u32 {
+        signal.chars().fold(0, |acc, char| {
+            let wire = (char as u32).wrapping_sub('a' as u32);
+            assert!(
+                (wire as usize) < self.segments.len(),
+                "Unknown wire {}",
+                char
+            );
+            acc | 1 << wire
+        })
+    }
+
+    fn glyph(&self, lit: u32) -> Option<char> {
+        self.glyphs
+            .iter()
+            .find(|(_, glyph_lit)| *glyph_lit == lit)
+            .map(|(glyph, _)| *glyph)
+    }
+
+    /// An entry in the puzzle's format that shows `text`, with the wires
+    /// randomly connected to the segments. The signal patterns are every glyph
+    /// in a random order.
+    fn scrambled_entry(&self, rng: &mut Rng, text: &str) -> String {
+        // `wires[segment]` is the wire connected to `segment`.
+        let mut wires = ('a'..='z').take(self.segments.len()).collect::<Vec<char>>();
+        rng.shuffle(&mut wires);
+
+        let mut to_signal = |lit: u32| {
+            let mut signal = (0..wires.len())
+                .filter(|segment| lit & 1 << segment != 0)
+                .map(|segment| wires[segment])
+                .collect::<Vec<char>>();
+            rng.shuffle(&mut signal);
+            signal.into_iter().collect::<String>()
+        };
+
+        let mut signal_patterns = self
+            .glyphs
+            .iter()
+            .map(|(_, lit)| to_signal(*lit))
+            .collect::<Vec<String>>();
+
+        let outputs = text
+            .chars()
+            .map(|char| {
+                let (_, lit) = self
+                    .glyphs
+                    .iter()
+                    .find(|(glyph, _)| *glyph == char)
+                    .unwrap_or_else(|| panic!("Layout can't show {}", char));
+                to_signal(*lit)
+            })
+            .collect::<Vec<String>>();
+
+        rng.shuffle(&mut signal_patterns);
+
+        format!("{} | {}", signal_patterns.join(" "), outputs.join(" "))
+    }
 
-        for (char, positions) in next_possibilities.iter_mut() {
-            if char == candidate {
-                continue;
+    /// Finds a wiring under which every signal shows one of its glyphs, where
+    /// `wiring[wire]` is the segment that `wire` is connected to.
+    ///
+    /// Each signal comes with the glyphs it may show, with bit `i` for glyph
+    /// `i`.
+    fn find_wiring(&self, signals: &[(u32, u64)]) -> Option<Vec<usize>> {
+        let mut result = None;
+        self.for_each_wiring(signals, &mut |wiring| {
+            result = Some(wiring.to_vec());
+            ControlFlow::Break(())
+        });
+        result
+    }
+
+    /// Calls `on_wiring` with every wiring under which every signal shows one
+    /// of its glyphs, until it breaks.
+    ///
+    /// Each wire starts out able to be any segment that could explain the
+    /// signals it is in, and the rest are found by backtracking, trying the
+    /// wire with the fewest options first.
+    fn for_each_wiring(
+        &self,
+        signals: &[(u32, u64)],
+        on_wiring: &mut dyn FnMut(&[usize]) -> ControlFlow<()>,
+    ) {
+        let num_segments = self.segments.len();
+        let all_segments = (1u32 << num_segments) - 1;
+
+        let mut options = vec![all_segments; num_segments];
+        for (signal, allowed) in signals {
+            let candidates = self.candidates(*signal, *allowed);
+            let union = candidates.clone().fold(0, |acc, lit| acc | lit);
+            let intersection = candidates.fold(all_segments, |acc, lit| acc & lit);
+
+            for (wire, wire_options) in options.iter_mut().enumerate() {
+                if signal & 1 << wire != 0 {
+                    *wire_options &= union;
+                } else {
+                    *wire_options &= !intersection;
+                }
             }
+        }
+
+        let mut wiring = vec![None; num_segments];
+        let _ = self.assign_wires(signals, &options, &mut wiring, 0, on_wiring);
+    }
 
-            positions.retain(|x| x != guess);
+    fn assign_wires(
+        &self,
+        signals: &[(u32, u64)],
+        options: &[u32],
+        wiring: &mut [Option<usize>],
+        used: u32,
+        on_wiring: &mut dyn FnMut(&[usize]) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
+        let next_wire = (0..wiring.len())
+            .filter(|wire| wiring[*wire].is_none())
+            .min_by_key(|wire| (options[*wire] & !used).count_ones());
+
+        let Some(wire) = next_wire else {
+            return on_wiring(
+                &wiring
+                    .iter()
+                    .map(|segment| segment.unwrap())
+                    .collect::<Vec<_>>(),
+            );
+        };
+
+        let mut remaining = options[wire] & !used;
+        while remaining != 0 {
+            let segment = remaining.trailing_zeros() as usize;
+            remaining &= remaining - 1;
+
+            wiring[wire] = Some(segment);
+            if self.is_consistent(signals, wiring) {
+                let result =
+                    self.assign_wires(signals, options, wiring, used | 1 << segment, on_wiring);
+                if result.is_break() {
+                    wiring[wire] = None;
+                    return result;
+                }
+            }
         }
 
-        if let Some(solution) = backtrack(valid_check, next_possibilities) {
-            return Some(solution);
+        wiring[wire] = None;
+        ControlFlow::Continue(())
+    }
+
+    /// Whether every signal could still be one of its glyphs given the wires
+    /// assigned so far.
+    fn is_consistent(&self, signals: &[(u32, u64)], wiring: &[Option<usize>]) -> bool {
+        signals.iter().all(|(signal, allowed)| {
+            let (lit, unlit) = wiring.iter().enumerate().fold(
+                (0, 0),
+                |(lit, unlit), (wire, segment)| match segment {
+                    Some(segment) if signal & 1 << wire != 0 => (lit | 1 << segment, unlit),
+                    Some(segment) => (lit, unlit | 1 << segment),
+                    None => (lit, unlit),
+                },
+            );
+
+            self.candidates(*signal, *allowed)
+                .any(|glyph_lit| glyph_lit & lit == lit && glyph_lit & unlit == 0)
+        })
+    }
+
+    /// The segments of the glyphs in `allowed` that `signal` could show.
+    fn candidates(&self, signal: u32, allowed: u64) -> impl Iterator<Item = u32> + Clone + '_ {
+        self.glyphs
+            .iter()
+            .enumerate()
+            .filter(move |(i, (_, lit))| {
+                allowed & 1 << i != 0 && lit.count_ones() == signal.count_ones()
+            })
+            .map(|(_, (_, lit))| *lit)
+    }
+
+    /// The segments lit by `wires` under `wiring`.
+    fn to_segments(wiring: &[usize], wires: u32) -> u32 {
+        wiring
+            .iter()
+            .enumerate()
+            .filter(|(wire, _)| wires & 1 << wire != 0)
+            .fold(0, |acc, (_, segment)| acc | 1 << segment)
+    }
+
+    /// Decodes the outputs of an entry, or `None` if no wiring shows every
+    /// signal as a glyph.
+    #[allow(dead_code)]
+    fn decode(&self, signal_patterns: &[String], outputs: &[String]) -> Option<String> {
+        let signals = signal_patterns
+            .iter()
+            .chain(outputs.iter())
+            .map(|signal| (self.to_wires(signal), u64::MAX))
+            .collect::<Vec<_>>();
+
+        let wiring = self.find_wiring(&signals)?;
+
+        outputs
+            .iter()
+            .map(|output| self.glyph(Self::to_segments(&wiring, self.to_wires(output))))
+            .collect()
+    }
+
+    /// Works out every reading of the outputs of an entry under wirings that
+    /// show every signal as a glyph.
+    #[allow(dead_code)]
+    fn diagnose(&self, signal_patterns: &[String], outputs: &[String]) -> EntryDiagnosis {
+        let patterns = signal_patterns
+            .iter()
+            .chain(outputs.iter())
+            .collect::<Vec<&String>>();
+        let mut signals = patterns
+            .iter()
+            .map(|signal| (self.to_wires(signal), u64::MAX))
+            .collect::<Vec<_>>();
+
+        if self.find_wiring(&signals).is_none() {
+            // Add one signal at a time to find the one that breaks.
+            let pattern = (0..signals.len())
+                .find(|i| self.find_wiring(&signals[..=*i]).is_none())
+                .map(|i| patterns[i].to_string())
+                .expect("Some signal should have no wiring");
+
+            return EntryDiagnosis::Inconsistent { pattern };
+        }
+
+        let mut readings = BTreeSet::new();
+        self.add_readings(
+            &mut signals,
+            signal_patterns.len(),
+            &mut String::new(),
+            &mut readings,
+        );
+
+        let mut readings = readings.into_iter().collect::<Vec<_>>();
+        if readings.len() == 1 {
+            EntryDiagnosis::Unique(readings.remove(0))
+        } else {
+            EntryDiagnosis::Ambiguous(readings)
         }
     }
 
-    None
-}
+    /// Adds to `readings` every way to read the outputs from `signals[output]`
+    /// on, given that the outputs before it read `reading`.
+    ///
+    /// Each output is pinned to one glyph at a time, and only kept if some
+    /// wiring still shows it, so the search grows with the number of readings
+    /// rather than the number of wirings.
+    fn add_readings(
+        &self,
+        signals: &mut [(u32, u64)],
+        output: usize,
+        reading: &mut String,
+        readings: &mut BTreeSet<String>,
+    ) {
+        if output == signals.len() {
+            readings.insert(reading.clone());
+            return;
+        }
 
-fn signal_to_number(solution: &HashMap<char, HashSet<u8>>, signal: &str) -> Option<u8> {
-    let solution: HashMap<char, u8> = HashMap::from_iter(
-        solution
-            .into_iter()
-            .map(|(k, v)| (*k, *v.into_iter().next().unwrap())),
-    );
+        for (i, (glyph, _)) in self.glyphs.iter().enumerate() {
+            signals[output].1 = 1 << i;
+            if self.find_wiring(signals).is_some() {
+                reading.push(*glyph);
+                self.add_readings(signals, output + 1, reading, readings);
+                reading.pop();
+            }
+        }
+        signals[output].1 = u64::MAX;
+    }
+}
 
-    let positions: HashSet<u8> =
-        HashSet::from_iter(signal.chars().map(|char| *solution.get(&char).unwrap()));
+/// Generates puzzle lines that show `digits`, 4 to a line, each with its own
+/// random wiring. Returns each line with the output value it should decode to.
+#[allow(dead_code)]
+fn generate_entries(seed: u64, digits: &[u8]) -> Vec<(String, u64)> {
+    assert!(digits.len().is_multiple_of(4), "Each line shows 4 digits");
+
+    let layout = DisplayLayout::seven_segment();
+    let mut rng = Rng::new(seed);
+
+    digits
+        .chunks(4)
+        .map(|chunk| {
+            let text = chunk
+                .iter()
+                .map(|digit| {
+                    char::from_digit(*digit as u32, 10)
+                        .unwrap_or_else(|| panic!("{} is not a digit", digit))
+                })
+                .collect::<String>();
+
+            let value = chunk.iter().fold(0, |acc, digit| acc * 10 + *digit as u64);
+
+            (layout.scrambled_entry(&mut rng, &text), value)
+        })
+        .collect()
+}
 
-    positions_to_number(&positions)
+/// What can be said about the outputs of an entry.
+#[derive(Debug, Clone, PartialEq)]
+enum EntryDiagnosis {
+    /// Every wiring that shows every signal as a glyph gives the same reading.
+    Unique(String),
+    /// The distinct readings, in order.
+    Ambiguous(Vec<String>),
+    /// `pattern` can't be any glyph together with the patterns before it, so
+    /// the entry has no wiring.
+    Inconsistent { pattern: String },
 }
 
+/// Digits followed by letters, with bits in the order of the segments in
+/// [`DisplayLayout::fourteen_segment`].
+const FOURTEEN_SEGMENT_GLYPHS: [(char, u32); 36] = [
+    ('0', 0x0c3f),
+    ('1', 0x0006),
+    ('2', 0x00db),
+    ('3', 0x008f),
+    ('4', 0x00e6),
+    ('5', 0x2069),
+    ('6', 0x00fd),
+    ('7', 0x0007),
+    ('8', 0x00ff),
+    ('9', 0x00ef),
+    ('A', 0x00f7),
+    ('B', 0x128f),
+    ('C', 0x0039),
+    ('D', 0x120f),
+    ('E', 0x00f9),
+    ('F', 0x0071),
+    ('G', 0x00bd),
+    ('H', 0x00f6),
+    ('I', 0x1209),
+    ('J', 0x001e),
+    ('K', 0x2470),
+    ('L', 0x0038),
+    ('M', 0x0536),
+    ('N', 0x2136),
+    ('O', 0x003f),
+    ('P', 0x00f3),
+    ('Q', 0x203f),
+    ('R', 0x20f3),
+    ('S', 0x018d),
+    ('T', 0x1201),
+    ('U', 0x003e),
+    ('V', 0x0c30),
+    ('W', 0x2836),
+    ('X', 0x2d00),
+    ('Y', 0x1500),
+    ('Z', 0x0c09),
+];
+
 #[cfg(test)]
 mod tests {
     use crate::common::read_input;
@@ -262,4 +651,347 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
             assert_eq!(all_output_values_sum(&parse_input()), 986163,);
         }
     }
+
+    mod deduce_digits {
+        use crate::day_08::{decode_entry, deduce_digits, tests::parse_input_str, to_pattern};
+
+        use super::EXAMPLE;
+
+        #[test]
+        fn example_small_test() {
+            let (signal_patterns, outputs) = parse_input_str(
+                "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+            );
+
+            let digits = deduce_digits(&signal_patterns);
+            for (signal, digit) in [
+                ("acedgfb", 8),
+                ("cdfbe", 5),
+                ("gcdfa", 2),
+                ("fbcad", 3),
+                ("dab", 7),
+                ("cefabd", 9),
+                ("cdfgeb", 6),
+                ("eafb", 4),
+                ("cagedb", 0),
+                ("ab", 1),
+            ] {
+                assert_eq!(
+                    digits[to_pattern(signal) as usize],
+                    Some(digit),
+                    "{}",
+                    signal
+                );
+            }
+            assert_eq!(decode_entry(&signal_patterns, &outputs), 5353);
+        }
+
+        #[test]
+        fn pattern_order_test() {
+            // Wires within a pattern can be in any order.
+            assert_eq!(to_pattern("ab"), to_pattern("ba"));
+            assert_eq!(to_pattern("abcdefg"), 0b111_1111);
+        }
+
+        #[test]
+        fn example_test() {
+            let expected = [8394, 9781, 1197, 9361, 4873, 8418, 4548, 1625, 8717, 4315];
+
+            for (line, expected) in EXAMPLE.lines().zip(expected) {
+                let (signal_patterns, outputs) = parse_input_str(line);
+                assert_eq!(decode_entry(&signal_patterns, &outputs), expected);
+            }
+        }
+    }
+
+    mod display_layout {
+        use crate::common::rng::Rng;
+        use crate::day_08::{
+            decode_entry, tests::parse_input_str, DisplayLayout, FOURTEEN_SEGMENT_GLYPHS,
+        };
+
+        use super::{parse_input, EXAMPLE};
+
+        fn scramble(layout: &DisplayLayout, text: &str, seed: u64) -> (Vec<String>, Vec<String>) {
+            parse_input_str(&layout.scrambled_entry(&mut Rng::new(seed), text))
+        }
+
+        #[test]
+        fn seven_segment_test() {
+            let layout = DisplayLayout::seven_segment();
+
+            for line in EXAMPLE.lines() {
+                let (signal_patterns, outputs) = parse_input_str(line);
+                let expected = decode_entry(&signal_patterns, &outputs);
+
+                assert_eq!(
+                    layout.decode(&signal_patterns, &outputs),
+                    Some(format!("{:04}", expected))
+                );
+            }
+        }
+
+        #[test]
+        fn fourteen_segment_test() {
+            let layout = DisplayLayout::fourteen_segment();
+
+            for (seed, text) in ["HELLO", "W0RLD", "AZ19", "QUICK8"].into_iter().enumerate() {
+                let (signal_patterns, outputs) = scramble(&layout, text, seed as u64);
+                assert_eq!(
+                    layout.decode(&signal_patterns, &outputs),
+                    Some(text.to_string())
+                );
+            }
+        }
+
+        #[test]
+        fn segment_names_test() {
+            // Built from the diagram on `fourteen_segment`.
+            let by_name = DisplayLayout::from_segment_names(
+                DisplayLayout::fourteen_segment().segments,
+                &[
+                    ('K', &["e", "f", "g1", "k", "n"][..]),
+                    ('N', &["b", "c", "e", "f", "h", "n"]),
+                    ('Z', &["a", "d", "k", "l"]),
+                    ('5', &["a", "d", "f", "g1", "n"]),
+                ],
+            );
+
+            for (glyph, lit) in by_name.glyphs {
+                assert!(FOURTEEN_SEGMENT_GLYPHS.contains(&(glyph, lit)), "{}", glyph);
+            }
+
+            // The same glyphs from the diagram on `sixteen_segment`.
+            let layout = DisplayLayout::sixteen_segment();
+            let by_name = DisplayLayout::from_segment_names(
+                layout.segments.clone(),
+                &[
+                    ('K', &["e", "f", "g1", "k", "n"][..]),
+                    ('N', &["b", "c", "e", "f", "h", "n"]),
+                    ('Z', &["a1", "a2", "d1", "d2", "k", "l"]),
+                    ('5', &["a1", "a2", "d1", "d2", "f", "g1", "n"]),
+                ],
+            );
+
+            for (glyph, lit) in by_name.glyphs {
+                assert_eq!(layout.glyph(lit), Some(glyph));
+            }
+        }
+
+        #[test]
+        fn sixteen_segment_test() {
+            let layout = DisplayLayout::sixteen_segment();
+
+            for (seed, text) in ["HELLO", "W0RLD", "AZ19", "QUICK8"].into_iter().enumerate() {
+                let (signal_patterns, outputs) = scramble(&layout, text, seed as u64);
+                assert_eq!(
+                    layout.decode(&signal_patterns, &outputs),
+                    Some(text.to_string())
+                );
+            }
+        }
+
+        #[test]
+        fn no_wiring_test() {
+            let layout = DisplayLayout::seven_segment();
+
+            // Two different patterns with 2 wires can't both be 1.
+            let signal_patterns = vec!["ab".to_string(), "cd".to_string()];
+            assert_eq!(layout.decode(&signal_patterns, &[]), None);
+        }
+
+        #[test]
+        #[should_panic(expected = "looks the same as another glyph")]
+        fn duplicate_glyph_test() {
+            DisplayLayout::new(vec!["a", "b"], vec![('x', 0b01), ('y', 0b01)]);
+        }
+
+        #[test]
+        fn solution() {
+            let layout = DisplayLayout::seven_segment();
+
+            let sum = parse_input()
+                .iter()
+                .map(|(signal_patterns, outputs)| {
+                    layout
+                        .decode(signal_patterns, outputs)
+                        .unwrap()
+                        .parse::<u64>()
+                        .unwrap()
+                })
+                .sum::<u64>();
+            assert_eq!(sum, 986163);
+        }
+    }
+
+    mod diagnose {
+        use crate::common::rng::Rng;
+        use crate::day_08::{tests::parse_input_str, DisplayLayout, EntryDiagnosis};
+
+        use super::{parse_input, EXAMPLE};
+
+        fn to_strings(patterns: &[&str]) -> Vec<String> {
+            patterns.iter().map(|pattern| pattern.to_string()).collect()
+        }
+
+        #[test]
+        fn unique_test() {
+            let layout = DisplayLayout::seven_segment();
+
+            for line in EXAMPLE.lines() {
+                let (signal_patterns, outputs) = parse_input_str(line);
+
+                assert_eq!(
+                    layout.diagnose(&signal_patterns, &outputs),
+                    EntryDiagnosis::Unique(layout.decode(&signal_patterns, &outputs).unwrap())
+                );
+            }
+
+            // Any wiring of 2 wires reads 1.
+            assert_eq!(
+                layout.diagnose(&[], &to_strings(&["ab", "ba"])),
+                EntryDiagnosis::Unique("11".to_string())
+            );
+        }
+
+        #[test]
+        fn ambiguous_test() {
+            let layout = DisplayLayout::seven_segment();
+
+            // 2 and 3 differ by one segment, and so do 3 and 5.
+            assert_eq!(
+                layout.diagnose(&[], &to_strings(&["abcde", "abcdf"])),
+                EntryDiagnosis::Ambiguous(to_strings(&["23", "32", "35", "53"]))
+            );
+
+            // Knowing which pattern is 1 means only 3 contains both its wires.
+            assert_eq!(
+                layout.diagnose(&to_strings(&["ab"]), &to_strings(&["abcde", "acdef"])),
+                EntryDiagnosis::Ambiguous(to_strings(&["32", "35"]))
+            );
+        }
+
+        #[test]
+        fn sixteen_segment_test() {
+            let layout = DisplayLayout::sixteen_segment();
+
+            // a1 and a2 are always lit together, as are d1 and d2, so there is
+            // more than one wiring, but they all read the same.
+            let entry = layout.scrambled_entry(&mut Rng::new(16), "HELLO");
+            let (signal_patterns, outputs) = parse_input_str(&entry);
+            assert_eq!(
+                layout.diagnose(&signal_patterns, &outputs),
+                EntryDiagnosis::Unique("HELLO".to_string())
+            );
+        }
+
+        #[test]
+        fn inconsistent_test() {
+            let layout = DisplayLayout::seven_segment();
+
+            // Two different patterns can't both be 1.
+            assert_eq!(
+                layout.diagnose(&to_strings(&["ab", "cd"]), &to_strings(&["ab"])),
+                EntryDiagnosis::Inconsistent {
+                    pattern: "cd".to_string()
+                }
+            );
+
+            // No digit has one segment.
+            assert_eq!(
+                layout.diagnose(&to_strings(&["ab"]), &to_strings(&["c"])),
+                EntryDiagnosis::Inconsistent {
+                    pattern: "c".to_string()
+                }
+            );
+
+            // An output that isn't one of the signal patterns.
+            let (signal_patterns, _) = parse_input_str(EXAMPLE.lines().next().unwrap());
+            assert_eq!(
+                layout.diagnose(&signal_patterns, &to_strings(&["cg"])),
+                EntryDiagnosis::Inconsistent {
+                    pattern: "cg".to_string()
+                }
+            );
+        }
+
+        #[test]
+        fn solution() {
+            let layout = DisplayLayout::seven_segment();
+
+            for (signal_patterns, outputs) in parse_input() {
+                assert!(matches!(
+                    layout.diagnose(&signal_patterns, &outputs),
+                    EntryDiagnosis::Unique(_)
+                ));
+            }
+        }
+    }
+
+    mod generate_entries {
+        use crate::common::rng::Rng;
+        use crate::day_08::{
+            all_output_values_sum, generate_entries, tests::parse_input_str, times_1_4_7_8_appear,
+            DisplayLayout, EntryDiagnosis,
+        };
+
+        #[test]
+        fn format_test() {
+            let entries = generate_entries(1, &[1, 2, 3, 4]);
+            assert_eq!(entries.len(), 1);
+
+            let (line, value) = &entries[0];
+            assert_eq!(*value, 1234);
+
+            let (signal_patterns, outputs) = line.split_once(" | ").unwrap();
+            assert_eq!(signal_patterns.split(' ').count(), 10);
+            assert_eq!(outputs.split(' ').count(), 4);
+            assert!(line
+                .chars()
+                .all(|char| ('a'..='g').contains(&char) || char == ' ' || char == '|'));
+
+            assert_eq!(generate_entries(1, &[1, 2, 3, 4]), entries);
+            assert_ne!(generate_entries(2, &[1, 2, 3, 4]), entries);
+        }
+
+        #[test]
+        fn decodes_test() {
+            let mut rng = Rng::new(40);
+            let digits = (0..4000).map(|_| rng.below(10) as u8).collect::<Vec<u8>>();
+
+            let entries = generate_entries(2021, &digits);
+            let input = entries
+                .iter()
+                .map(|(line, _)| parse_input_str(line))
+                .collect::<Vec<_>>();
+
+            assert_eq!(
+                all_output_values_sum(&input),
+                entries.iter().map(|(_, value)| value).sum::<u64>()
+            );
+            assert_eq!(
+                times_1_4_7_8_appear(&input),
+                digits
+                    .iter()
+                    .filter(|digit| [1, 4, 7, 8].contains(*digit))
+                    .count()
+            );
+
+            let layout = DisplayLayout::seven_segment();
+            for ((signal_patterns, outputs), (_, value)) in
+                input.iter().zip(entries.iter()).take(50)
+            {
+                assert_eq!(
+                    layout.diagnose(signal_patterns, outputs),
+                    EntryDiagnosis::Unique(format!("{:04}", value))
+                );
+            }
+        }
+
+        #[test]
+        #[should_panic(expected = "Each line shows 4 digits")]
+        fn partial_line_test() {
+            generate_entries(1, &[1, 2, 3]);
+        }
+    }
 }
@@ -1,6 +1,22 @@
-use std::collections::{BinaryHeap, HashSet};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    io::{self, Write},
+};
+
+use crate::common::rng::Rng;
 
 const DIRECTIONS: [[isize; 2]; 4] = [[0, -1], [1, 0], [0, 1], [-1, 0]];
+const DIRECTIONS_WITH_DIAGONALS: [[isize; 2]; 8] = [
+    [0, -1],
+    [1, -1],
+    [1, 0],
+    [1, 1],
+    [0, 1],
+    [-1, 1],
+    [-1, 0],
+    [-1, -1],
+];
 
 #[allow(dead_code)]
 fn risk_level_sum(input: &Vec<Vec<i8>>) -> u64 {
@@ -41,75 +57,744 @@ fn low_points(input: &Vec<Vec<i8>>) -> Vec<(usize, usize)> {
     result
 }
 
+/// Product of the sizes of the three largest basins.
 #[allow(dead_code)]
-fn basin_sizes(input: &Vec<Vec<i8>>) -> u64 {
-    let mut basins = BinaryHeap::new();
-
-    for low_point in low_points(input) {
-        let mut size = 0;
-
-        let mut visited: HashSet<(isize, isize)> = HashSet::new();
-        let mut queue = MinHeap::new();
-        queue.push((
-            // Cannot use i8::MIN because we are going to negate this in the
-            // MinHeap and it will overflow.
-            -i8::MAX,
-            (low_point.0 as isize, low_point.1 as isize),
-        ));
-
-        while let Some((prev, (x, y))) = queue.pop() {
-            if x < 0
-                || y < 0
-                || y >= input.len() as isize
-                || x >= input.get(y as usize).unwrap().len() as isize
-            {
+fn basin_sizes(input: &[Vec<i8>]) -> u64 {
+    basin_map(input).three_largest_product()
+}
+
+struct MinHeap(BinaryHeap<(i8, (isize, isize))>);
+
+impl MinHeap {
+    fn new() -> Self {
+        Self(BinaryHeap::new())
+    }
+    fn push(&mut self, (depth, coordinates): (i8, (isize, isize))) {
+        self.0.push((-depth, coordinates));
+    }
+
+    fn pop(&mut self) -> Option<(i8, (isize, isize))> {
+        self.0
+            .pop()
+            .map(|(negative_depth, coordinates)| (-negative_depth, coordinates))
+    }
+}
+
+/// Water left on the map after rain fills it and drains over the edges.
+#[derive(Debug, Clone, PartialEq)]
+struct RainFill {
+    volume: u64,
+    /// `depths[y][x]` is how deep the water is above the cell.
+    depths: Vec<Vec<i8>>,
+}
+
+/// Fills the map with rain and drains it over the edges.
+///
+/// Floods inwards from the edges, lowest first. The water level of a cell is
+/// the highest cell on the lowest path out, so each cell popped is enclosed by
+/// at most its own level.
+#[allow(dead_code)]
+fn rain_fill(input: &[Vec<i8>]) -> RainFill {
+    let mut depths = input
+        .iter()
+        .map(|row| vec![0; row.len()])
+        .collect::<Vec<Vec<i8>>>();
+
+    let mut visited: HashSet<(isize, isize)> = HashSet::new();
+    let mut queue = MinHeap::new();
+    for (y, row) in input.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let is_edge = DIRECTIONS.iter().any(|vector| {
+                let (neighbor_x, neighbor_y) = (x as isize + vector[0], y as isize + vector[1]);
+                neighbor_x < 0
+                    || neighbor_y < 0
+                    || neighbor_y >= input.len() as isize
+                    || neighbor_x >= input[neighbor_y as usize].len() as isize
+            });
+
+            if is_edge {
+                visited.insert((x as isize, y as isize));
+                queue.push((*cell, (x as isize, y as isize)));
+            }
+        }
+    }
+
+    while let Some((level, (x, y))) = queue.pop() {
+        for vector in DIRECTIONS {
+            let (next_x, next_y) = (x + vector[0], y + vector[1]);
+            // Cells off the map are edges and already visited.
+            let Some(next) = input
+                .get(next_y as usize)
+                .and_then(|row| row.get(next_x as usize))
+            else {
+                continue;
+            };
+
+            if !visited.insert((next_x, next_y)) {
                 continue;
             }
 
-            if visited.contains(&(x, y)) {
+            let next_level = level.max(*next);
+            depths[next_y as usize][next_x as usize] = next_level - next;
+            queue.push((next_level, (next_x, next_y)));
+        }
+    }
+
+    RainFill {
+        volume: depths.iter().flatten().map(|depth| *depth as u64).sum(),
+        depths,
+    }
+}
+
+/// A basin: cells that are not 9 and are connected without crossing a 9.
+#[derive(Debug, Clone, PartialEq)]
+struct Basin {
+    size: usize,
+    /// The lowest cell, or the first in reading order if there are several.
+    low_point: (usize, usize),
+    /// Cells in reading order.
+    cells: Vec<(usize, usize)>,
+}
+
+/// Every cell labelled with its basin.
+#[derive(Debug, Clone, PartialEq)]
+struct BasinMap {
+    /// `labels[y][x]` is the index of the basin of the cell into `basins`, or
+    /// `None` for 9s.
+    labels: Vec<Vec<Option<usize>>>,
+    /// Basins in the reading order of their first cell.
+    basins: Vec<Basin>,
+}
+
+impl BasinMap {
+    /// Product of the sizes of the three largest basins.
+    fn three_largest_product(&self) -> u64 {
+        let mut sizes = self
+            .basins
+            .iter()
+            .map(|basin| basin.size as u64)
+            .collect::<Vec<_>>();
+        sizes.sort_unstable_by(|a, b| b.cmp(a));
+
+        sizes.into_iter().take(3).product()
+    }
+}
+
+/// Disjoint sets of `0..n`, with path halving and union by size.
+struct UnionFind {
+    parents: Vec<usize>,
+    sizes: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parents: (0..n).collect(),
+            sizes: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parents[x] != x {
+            self.parents[x] = self.parents[self.parents[x]];
+            x = self.parents[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+
+        let (larger, smaller) = if self.sizes[a] >= self.sizes[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parents[smaller] = larger;
+        self.sizes[larger] += self.sizes[smaller];
+    }
+}
+
+/// Labels every cell that isn't a 9 with its basin, by joining each such cell
+/// with its right and lower neighbours.
+#[allow(dead_code)]
+fn basin_map(input: &[Vec<i8>]) -> BasinMap {
+    let width = input.iter().map(|row| row.len()).max().unwrap_or(0);
+    let index = |x: usize, y: usize| y * width + x;
+    let is_basin = |x: usize, y: usize| {
+        input
+            .get(y)
+            .and_then(|row| row.get(x))
+            .is_some_and(|cell| *cell != 9)
+    };
+
+    let mut sets = UnionFind::new(width * input.len());
+    for (y, row) in input.iter().enumerate() {
+        for x in 0..row.len() {
+            if !is_basin(x, y) {
                 continue;
             }
+            if is_basin(x + 1, y) {
+                sets.union(index(x, y), index(x + 1, y));
+            }
+            if is_basin(x, y + 1) {
+                sets.union(index(x, y), index(x, y + 1));
+            }
+        }
+    }
+
+    let mut labels = input
+        .iter()
+        .map(|row| vec![None; row.len()])
+        .collect::<Vec<Vec<Option<usize>>>>();
+    let mut basins: Vec<Basin> = Vec::new();
+    // Basin of each union-find root.
+    let mut root_labels = vec![None; width * input.len()];
 
-            let current = *input.get(y as usize).unwrap().get(x as usize).unwrap();
-            if current == 9 {
+    for (y, row) in input.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if *cell == 9 {
                 continue;
             }
 
-            visited.insert((x, y));
+            let root = sets.find(index(x, y));
+            let label = *root_labels[root].get_or_insert_with(|| {
+                basins.push(Basin {
+                    size: 0,
+                    low_point: (x, y),
+                    cells: Vec::new(),
+                });
+                basins.len() - 1
+            });
+
+            let basin = &mut basins[label];
+            basin.size += 1;
+            basin.cells.push((x, y));
+            let (low_x, low_y) = basin.low_point;
+            if *cell < input[low_y][low_x] {
+                basin.low_point = (x, y);
+            }
+
+            labels[y][x] = Some(label);
+        }
+    }
+
+    BasinMap { labels, basins }
+}
+
+/// A region of the map under water, in a [`MergeTree`].
+#[derive(Debug, Clone, PartialEq)]
+struct MergeNode {
+    /// Water level at which the region appears.
+    level: i8,
+    /// The first cell of a new region in reading order, or the saddle cell
+    /// that joined the children.
+    cell: (usize, usize),
+    /// Size of the region when it appears: one cell for a new region, or the
+    /// children and the saddle cell.
+    size: usize,
+    /// Regions that joined to form this one, empty for new regions.
+    children: Vec<usize>,
+    parent: Option<usize>,
+}
+
+/// How regions under water appear and join as the water level rises from 0 to
+/// 9.
+#[derive(Debug, Clone, PartialEq)]
+struct MergeTree {
+    nodes: Vec<MergeNode>,
+    /// `node_of_cells[y][x]` is the region the cell joined when it went under.
+    node_of_cells: Vec<Vec<usize>>,
+}
+
+impl MergeTree {
+    /// Regions that haven't joined another, which is one per connected part of
+    /// the map.
+    #[allow(dead_code)]
+    fn roots(&self) -> Vec<usize> {
+        (0..self.nodes.len())
+            .filter(|node| self.nodes[*node].parent.is_none())
+            .collect()
+    }
+
+    /// The region where the water over `a` and `b` first joins, if it ever
+    /// does.
+    #[allow(dead_code)]
+    fn connection(
+        &self,
+        (a_x, a_y): (usize, usize),
+        (b_x, b_y): (usize, usize),
+    ) -> Option<&MergeNode> {
+        let ancestors = |mut node: usize| {
+            let mut result = vec![node];
+            while let Some(parent) = self.nodes[node].parent {
+                result.push(parent);
+                node = parent;
+            }
+            result
+        };
+
+        let a_ancestors = ancestors(self.node_of_cells[a_y][a_x]);
+        let b_ancestors = ancestors(self.node_of_cells[b_y][b_x]);
+
+        a_ancestors
+            .into_iter()
+            .find(|node| b_ancestors.contains(node))
+            .map(|node| &self.nodes[node])
+    }
+}
+
+/// Raises the water level from 0 to 9 and records where regions under water
+/// appear and join.
+///
+/// At each level the cells next to regions already under water are flooded
+/// first, spreading across cells of that height, so a flat stretch joining two
+/// regions doesn't appear as a region of its own.
+#[allow(dead_code)]
+fn merge_tree(input: &[Vec<i8>]) -> MergeTree {
+    let width = input.iter().map(|row| row.len()).max().unwrap_or(0);
+    let index = |(x, y): (usize, usize)| y * width + x;
+
+    let mut sets = UnionFind::new(width * input.len());
+    // Region of each union-find root.
+    let mut node_of_roots = vec![0; width * input.len()];
+    let mut flooded = vec![false; width * input.len()];
+    let mut nodes: Vec<MergeNode> = Vec::new();
+    let mut node_of_cells = input
+        .iter()
+        .map(|row| vec![0; row.len()])
+        .collect::<Vec<Vec<usize>>>();
+
+    for level in 0..=9 {
+        let at_level = |(x, y): (usize, usize)| input[y][x] == level;
+        let is_next_to_water = |cell, flooded: &[bool]| {
+            neighbors(input, cell, Connectivity::Four).any(|neighbor| flooded[index(neighbor)])
+        };
+
+        let cells = input
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| (0..row.len()).map(move |x| (x, y)))
+            .filter(|cell| at_level(*cell))
+            .collect::<Vec<_>>();
+
+        // Cells next to water go first, so that they don't appear as new
+        // regions.
+        let mut queue = cells
+            .iter()
+            .copied()
+            .filter(|cell| is_next_to_water(*cell, &flooded))
+            .collect::<VecDeque<_>>();
+        let mut queued = queue.iter().copied().collect::<HashSet<_>>();
+        let mut new_regions = cells.iter().copied();
+
+        loop {
+            let cell = match queue.pop_front() {
+                Some(cell) => cell,
+                None => {
+                    // Whatever is left isn't next to water, so starts a new
+                    // region.
+                    let Some(cell) = new_regions.find(|cell| !queued.contains(cell)) else {
+                        break;
+                    };
+                    queued.insert(cell);
+
+                    nodes.push(MergeNode {
+                        level,
+                        cell,
+                        size: 1,
+                        children: Vec::new(),
+                        parent: None,
+                    });
+                    node_of_roots[sets.find(index(cell))] = nodes.len() - 1;
+                    cell
+                }
+            };
+
+            let mut joined = neighbors(input, cell, Connectivity::Four)
+                .filter(|neighbor| flooded[index(*neighbor)])
+                .map(|neighbor| sets.find(index(neighbor)))
+                .collect::<Vec<_>>();
+            joined.sort_unstable();
+            joined.dedup();
+
+            let children = joined
+                .iter()
+                .map(|root| node_of_roots[*root])
+                .collect::<Vec<_>>();
+            for root in joined {
+                sets.union(index(cell), root);
+            }
+            flooded[index(cell)] = true;
+            let root = sets.find(index(cell));
+
+            let node = match children.as_slice() {
+                [] => node_of_roots[root],
+                [child] => *child,
+                _ => {
+                    for child in children.iter() {
+                        nodes[*child].parent = Some(nodes.len());
+                    }
+                    nodes.push(MergeNode {
+                        level,
+                        cell,
+                        size: sets.sizes[root],
+                        children,
+                        parent: None,
+                    });
+                    nodes.len() - 1
+                }
+            };
+            node_of_roots[root] = node;
+            node_of_cells[cell.1][cell.0] = node;
+
+            for neighbor in neighbors(input, cell, Connectivity::Four) {
+                if at_level(neighbor) && queued.insert(neighbor) {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    MergeTree {
+        nodes,
+        node_of_cells,
+    }
+}
+
+/// Which cells count as neighbours.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Connectivity {
+    /// Up, down, left and right.
+    Four,
+    /// Diagonals as well.
+    Eight,
+}
+
+impl Connectivity {
+    fn directions(&self) -> &'static [[isize; 2]] {
+        match self {
+            Connectivity::Four => &DIRECTIONS,
+            Connectivity::Eight => &DIRECTIONS_WITH_DIAGONALS,
+        }
+    }
+}
+
+/// Which neighbours a basin flood spreads to. 9s are never part of a basin.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FloodRule {
+    /// Only to higher neighbours.
+    StrictlyAscending,
+    /// To higher neighbours and across flat ground.
+    NonDescending,
+    /// To any neighbour that isn't a 9.
+    BoundedByNines,
+}
+
+impl FloodRule {
+    fn spreads(&self, from: i8, to: i8) -> bool {
+        to != 9
+            && match self {
+                FloodRule::StrictlyAscending => to > from,
+                FloodRule::NonDescending => to >= from,
+                FloodRule::BoundedByNines => true,
+            }
+    }
+}
+
+/// Cells next to `(x, y)` that are on the map.
+fn neighbors(
+    input: &[Vec<i8>],
+    (x, y): (usize, usize),
+    connectivity: Connectivity,
+) -> impl Iterator<Item = (usize, usize)> + '_ {
+    connectivity.directions().iter().filter_map(move |vector| {
+        let neighbor_x = x.checked_add_signed(vector[0])?;
+        let neighbor_y = y.checked_add_signed(vector[1])?;
+        input.get(neighbor_y)?.get(neighbor_x)?;
+
+        Some((neighbor_x, neighbor_y))
+    })
+}
+
+/// Low points that may be flat: connected regions of equal height, other than
+/// 9s, with no lower neighbour.
+///
+/// A single cell region is a low point in the sense of [`low_points`]. Regions
+/// are in the reading order of their first cell, and their cells in reading
+/// order.
+#[allow(dead_code)]
+fn low_plateaus(input: &[Vec<i8>], connectivity: Connectivity) -> Vec<Vec<(usize, usize)>> {
+    let mut visited: HashSet<(usize, usize)> = HashSet::new();
+    let mut result = Vec::new();
 
-            if current <= prev {
+    for (y, row) in input.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            if *cell == 9 || visited.contains(&(x, y)) {
                 continue;
             }
 
-            size += 1;
+            let mut region = Vec::new();
+            let mut is_low = true;
+            let mut stack = vec![(x, y)];
+            visited.insert((x, y));
+
+            while let Some(current) = stack.pop() {
+                region.push(current);
+
+                for (neighbor_x, neighbor_y) in neighbors(input, current, connectivity) {
+                    let neighbor = input[neighbor_y][neighbor_x];
+                    if neighbor < *cell {
+                        is_low = false;
+                    } else if neighbor == *cell && visited.insert((neighbor_x, neighbor_y)) {
+                        stack.push((neighbor_x, neighbor_y));
+                    }
+                }
+            }
 
-            for vector in DIRECTIONS {
-                let (next_x, next_y) = (x as isize + vector[0], y as isize + vector[1]);
-                queue.push((current, (next_x, next_y)));
+            if is_low {
+                region.sort_by_key(|(x, y)| (*y, *x));
+                result.push(region);
             }
         }
+    }
+
+    result
+}
+
+/// Size of the basin flowing down to each of the [`low_plateaus`], in the same
+/// order.
+///
+/// Basins can overlap under every rule, so a cell may be counted in more than
+/// one basin. In `01210`, both low points reach the `2`.
+#[allow(dead_code)]
+fn basin_sizes_with_rule(
+    input: &[Vec<i8>],
+    rule: FloodRule,
+    connectivity: Connectivity,
+) -> Vec<u64> {
+    low_plateaus(input, connectivity)
+        .into_iter()
+        .map(|plateau| {
+            let mut visited = plateau.iter().copied().collect::<HashSet<_>>();
+            let mut stack = plateau;
+
+            while let Some((x, y)) = stack.pop() {
+                for (neighbor_x, neighbor_y) in neighbors(input, (x, y), connectivity) {
+                    if rule.spreads(input[y][x], input[neighbor_y][neighbor_x])
+                        && visited.insert((neighbor_x, neighbor_y))
+                    {
+                        stack.push((neighbor_x, neighbor_y));
+                    }
+                }
+            }
+
+            visited.len() as u64
+        })
+        .collect()
+}
+
+/// Writes the heightmap as a plain (ASCII) greyscale PGM image, with 0 black
+/// and 9 white.
+#[allow(dead_code)]
+fn write_heightmap_pgm(input: &[Vec<i8>], out: &mut impl Write) -> io::Result<()> {
+    let width = input.iter().map(|row| row.len()).max().unwrap_or(0);
+    writeln!(out, "P2\n{} {}\n9", width, input.len())?;
+
+    for row in input {
+        // Short rows are padded with 9s.
+        let samples = (0..width).map(|x| row.get(x).unwrap_or(&9).to_string());
+        write_samples(out, samples)?;
+    }
+    Ok(())
+}
+
+/// Writes the basins as a plain (ASCII) colour PPM image. Every basin gets its
+/// own colour, 9s are black and low points are white.
+#[allow(dead_code)]
+fn write_basins_ppm(map: &BasinMap, out: &mut impl Write) -> io::Result<()> {
+    let width = map.labels.iter().map(|row| row.len()).max().unwrap_or(0);
+    writeln!(out, "P3\n{} {}\n255", width, map.labels.len())?;
+
+    let low_points = map
+        .basins
+        .iter()
+        .map(|basin| basin.low_point)
+        .collect::<HashSet<_>>();
 
-        basins.push(size);
+    for (y, row) in map.labels.iter().enumerate() {
+        let samples = (0..width).flat_map(|x| {
+            let [r, g, b] = match row.get(x).copied().flatten() {
+                None => [0, 0, 0],
+                Some(_) if low_points.contains(&(x, y)) => [255, 255, 255],
+                Some(label) => basin_colour(label),
+            };
+            [r, g, b].map(|channel| channel.to_string())
+        });
+        write_samples(out, samples)?;
     }
+    Ok(())
+}
 
-    basins.into_iter().take(3).fold(1, |acc, x| acc * x)
+/// A colour for a basin label that is the same every time and is never close
+/// to black or white.
+fn basin_colour(label: usize) -> [u8; 3] {
+    let bits = Rng::new(label as u64).next_u64();
+    [0, 8, 16].map(|shift| 48 + ((bits >> shift) & 0xff) as u8 % 160)
 }
 
-struct MinHeap(BinaryHeap<(i8, (isize, isize))>);
+/// Writes samples separated by spaces, wrapping lines before they exceed the 70
+/// characters allowed by the plain netpbm formats.
+fn write_samples(out: &mut impl Write, samples: impl Iterator<Item = String>) -> io::Result<()> {
+    let mut line_length = 0;
 
-impl MinHeap {
-    fn new() -> Self {
-        Self(BinaryHeap::new())
+    for sample in samples {
+        if line_length > 0 && line_length + 1 + sample.len() > 70 {
+            writeln!(out)?;
+            line_length = 0;
+        }
+        if line_length > 0 {
+            write!(out, " ")?;
+            line_length += 1;
+        }
+        write!(out, "{}", sample)?;
+        line_length += sample.len();
     }
-    fn push(&mut self, (depth, coordinates): (i8, (isize, isize))) {
-        self.0.push((-depth, coordinates));
+    writeln!(out)
+}
+
+/// Calls `visit` with the coordinates and height of every low point, reading
+/// the map a row at a time and keeping only three rows.
+#[allow(dead_code)]
+fn streaming_low_points(
+    rows: impl IntoIterator<Item = Vec<i8>>,
+    mut visit: impl FnMut((usize, usize), i8),
+) {
+    let mut rows = rows.into_iter();
+    let mut above: Option<Vec<i8>> = None;
+    let mut current = rows.next();
+    let mut y = 0;
+
+    while let Some(row) = current {
+        let below = rows.next();
+
+        for (x, cell) in row.iter().enumerate() {
+            let neighbors = [
+                above.as_ref().and_then(|above| above.get(x)),
+                x.checked_sub(1).and_then(|left| row.get(left)),
+                row.get(x + 1),
+                below.as_ref().and_then(|below| below.get(x)),
+            ];
+
+            if neighbors
+                .into_iter()
+                .flatten()
+                .all(|neighbor| cell < neighbor)
+            {
+                visit((x, y), *cell);
+            }
+        }
+
+        above = Some(row);
+        current = below;
+        y += 1;
     }
+}
 
-    fn pop(&mut self) -> Option<(i8, (isize, isize))> {
-        self.0
-            .pop()
-            .map(|(negative_depth, coordinates)| (-negative_depth, coordinates))
+/// [`risk_level_sum`] reading the map a row at a time.
+#[allow(dead_code)]
+fn streaming_risk_level_sum(rows: impl IntoIterator<Item = Vec<i8>>) -> u64 {
+    let mut sum = 0;
+    streaming_low_points(rows, |_, height| sum += 1 + height as u64);
+    sum
+}
+
+/// Calls `visit` with the size of every basin bounded by 9s, reading the map a
+/// row at a time.
+///
+/// Only the labels of the previous row are kept. The cells of a row are joined
+/// with their left and upper neighbours, a basin is finished when no cell of
+/// the row joins it, and the labels still in use are renumbered from 0 so they
+/// don't grow with the number of rows.
+#[allow(dead_code)]
+fn streaming_basin_sizes(rows: impl IntoIterator<Item = Vec<i8>>, mut visit: impl FnMut(u64)) {
+    // Labels of the previous row, and the size so far of each label's basin.
+    let mut above: Vec<Option<usize>> = Vec::new();
+    let mut sizes: Vec<u64> = Vec::new();
+
+    for row in rows {
+        // Labels of this row start after those of the previous one.
+        let first_label = sizes.len();
+        let mut sets = UnionFind::new(first_label + row.len());
+        sizes.resize(first_label + row.len(), 0);
+
+        let mut current: Vec<Option<usize>> = vec![None; row.len()];
+        for (x, cell) in row.iter().enumerate() {
+            if *cell == 9 {
+                continue;
+            }
+
+            let label = first_label + x;
+            sizes[label] = 1;
+            current[x] = Some(label);
+
+            let left = x.checked_sub(1).and_then(|left| current[left]);
+            let up = above.get(x).copied().flatten();
+            for neighbor in [left, up].into_iter().flatten() {
+                let (a, b) = (sets.find(label), sets.find(neighbor));
+                if a != b {
+                    let size = sizes[a] + sizes[b];
+                    sets.union(a, b);
+                    sizes[sets.find(a)] = size;
+                }
+            }
+        }
+
+        // Renumber the basins this row is part of.
+        let mut compacted: HashMap<usize, usize> = HashMap::new();
+        let mut compacted_sizes = Vec::new();
+        for label in current.iter_mut().flatten() {
+            let root = sets.find(*label);
+            *label = *compacted.entry(root).or_insert_with(|| {
+                compacted_sizes.push(sizes[root]);
+                compacted_sizes.len() - 1
+            });
+        }
+
+        // Basins of the previous row that this row doesn't continue.
+        let mut finished = HashSet::new();
+        for label in above.iter().flatten() {
+            let root = sets.find(*label);
+            if !compacted.contains_key(&root) && finished.insert(root) {
+                visit(sizes[root]);
+            }
+        }
+
+        above = current;
+        sizes = compacted_sizes;
     }
+
+    sizes.into_iter().for_each(visit);
+}
+
+/// Product of the sizes of the three largest basins, reading the map a row at
+/// a time.
+#[allow(dead_code)]
+fn streaming_three_largest_product(rows: impl IntoIterator<Item = Vec<i8>>) -> u64 {
+    let mut largest = BinaryHeap::new();
+    streaming_basin_sizes(rows, |size| {
+        largest.push(Reverse(size));
+        if largest.len() > 3 {
+            largest.pop();
+        }
+    });
+
+    largest.into_iter().map(|Reverse(size)| size).product()
 }
 
 #[cfg(test)]
@@ -171,4 +856,434 @@ mod tests {
             assert_eq!(basin_sizes(&input), 1280496);
         }
     }
+
+    mod basin_map {
+        use super::{
+            super::{basin_map, low_points},
+            parse_input, parse_input_str, EXAMPLE,
+        };
+
+        #[test]
+        fn example_test() {
+            let input = parse_input_str(EXAMPLE);
+            let map = basin_map(&input);
+
+            assert_eq!(
+                map.basins
+                    .iter()
+                    .map(|basin| (basin.size, basin.low_point))
+                    .collect::<Vec<_>>(),
+                vec![(3, (1, 0)), (9, (9, 0)), (14, (2, 2)), (9, (6, 4))]
+            );
+            assert_eq!(map.basins[0].cells, vec![(0, 0), (1, 0), (0, 1)]);
+            assert_eq!(map.labels[0][0], Some(0));
+            assert_eq!(map.labels[0][2], None);
+            assert_eq!(map.labels[4][9], Some(3));
+
+            assert_eq!(map.three_largest_product(), 1134);
+        }
+
+        #[test]
+        fn every_cell_labelled_test() {
+            let input = parse_input_str(EXAMPLE);
+            let map = basin_map(&input);
+
+            for (y, row) in input.iter().enumerate() {
+                for (x, cell) in row.iter().enumerate() {
+                    assert_eq!(map.labels[y][x].is_none(), *cell == 9);
+
+                    if let Some(label) = map.labels[y][x] {
+                        assert!(map.basins[label].cells.contains(&(x, y)));
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn empty_test() {
+            let map = basin_map(&[]);
+            assert!(map.basins.is_empty());
+            assert_eq!(map.three_largest_product(), 1);
+
+            let map = basin_map(&[vec![9, 9]]);
+            assert!(map.basins.is_empty());
+        }
+
+        #[test]
+        fn solution() {
+            let input = parse_input();
+            let map = basin_map(&input);
+
+            assert_eq!(map.three_largest_product(), 1280496);
+
+            let mut basin_low_points = map
+                .basins
+                .iter()
+                .map(|basin| basin.low_point)
+                .collect::<Vec<_>>();
+            basin_low_points.sort_by_key(|(x, y)| (*y, *x));
+            assert_eq!(basin_low_points, low_points(&input));
+        }
+    }
+
+    mod flood_rules {
+        use super::{
+            super::{
+                basin_sizes, basin_sizes_with_rule, low_plateaus, low_points, Connectivity,
+                FloodRule,
+            },
+            parse_input, parse_input_str, EXAMPLE,
+        };
+
+        #[test]
+        fn low_plateaus_test() {
+            let input = parse_input_str(EXAMPLE);
+            assert_eq!(
+                low_plateaus(&input, Connectivity::Four),
+                low_points(&input)
+                    .into_iter()
+                    .map(|low_point| vec![low_point])
+                    .collect::<Vec<_>>()
+            );
+
+            // A flat bottom has no strict low point.
+            let input = parse_input_str("9999\n9119\n9129\n9999");
+            assert!(low_points(&input).is_empty());
+            assert_eq!(
+                low_plateaus(&input, Connectivity::Four),
+                vec![vec![(1, 1), (2, 1), (1, 2)]]
+            );
+        }
+
+        #[test]
+        fn connectivity_test() {
+            let input = parse_input_str("191\n919\n191");
+
+            assert_eq!(low_plateaus(&input, Connectivity::Four).len(), 5);
+            assert_eq!(
+                low_plateaus(&input, Connectivity::Eight),
+                vec![vec![(0, 0), (2, 0), (1, 1), (0, 2), (2, 2)]]
+            );
+            assert_eq!(
+                basin_sizes_with_rule(&input, FloodRule::BoundedByNines, Connectivity::Eight),
+                vec![5]
+            );
+        }
+
+        #[test]
+        fn flood_rule_test() {
+            let input = parse_input_str("01121");
+            let sizes = |rule| basin_sizes_with_rule(&input, rule, Connectivity::Four);
+
+            assert_eq!(sizes(FloodRule::StrictlyAscending), vec![2, 2]);
+            assert_eq!(sizes(FloodRule::NonDescending), vec![4, 2]);
+            assert_eq!(sizes(FloodRule::BoundedByNines), vec![5, 5]);
+
+            // The 2 is in both basins.
+            let input = parse_input_str("01210");
+            assert_eq!(
+                basin_sizes_with_rule(&input, FloodRule::StrictlyAscending, Connectivity::Four),
+                vec![3, 3]
+            );
+
+            let input = parse_input_str("9999\n9119\n9129\n9999");
+            assert_eq!(
+                basin_sizes_with_rule(&input, FloodRule::StrictlyAscending, Connectivity::Four),
+                vec![4]
+            );
+        }
+
+        #[test]
+        fn example_test() {
+            let input = parse_input_str(EXAMPLE);
+            assert_eq!(
+                basin_sizes_with_rule(&input, FloodRule::StrictlyAscending, Connectivity::Four),
+                vec![3, 9, 14, 9]
+            );
+        }
+
+        #[test]
+        fn solution() {
+            let input = parse_input();
+
+            let mut sizes =
+                basin_sizes_with_rule(&input, FloodRule::StrictlyAscending, Connectivity::Four);
+            sizes.sort_unstable_by(|a, b| b.cmp(a));
+            assert_eq!(sizes.iter().take(3).product::<u64>(), basin_sizes(&input));
+        }
+    }
+
+    mod images {
+        use super::{
+            super::{basin_colour, basin_map, write_basins_ppm, write_heightmap_pgm},
+            parse_input, parse_input_str, EXAMPLE,
+        };
+
+        fn to_string(write: impl FnOnce(&mut Vec<u8>) -> std::io::Result<()>) -> String {
+            let mut out = Vec::new();
+            write(&mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        }
+
+        /// Parses a plain netpbm image into its header and samples.
+        fn parse_netpbm(image: &str) -> (Vec<String>, Vec<u8>) {
+            let mut tokens = image.split_whitespace();
+            let header = tokens.by_ref().take(4).map(str::to_string).collect();
+            let samples = tokens.map(|token| token.parse().unwrap()).collect();
+            (header, samples)
+        }
+
+        #[test]
+        fn heightmap_pgm_test() {
+            let input = parse_input_str("219\n398");
+            assert_eq!(
+                to_string(|out| write_heightmap_pgm(&input, out)),
+                "P2\n3 2\n9\n2 1 9\n3 9 8\n"
+            );
+
+            let input = parse_input_str(EXAMPLE);
+            let (header, samples) =
+                parse_netpbm(&to_string(|out| write_heightmap_pgm(&input, out)));
+            assert_eq!(header, vec!["P2", "10", "5", "9"]);
+            assert_eq!(
+                samples,
+                input
+                    .concat()
+                    .into_iter()
+                    .map(|x| x as u8)
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        #[test]
+        fn basins_ppm_test() {
+            let input = parse_input_str(EXAMPLE);
+            let map = basin_map(&input);
+            let (header, samples) = parse_netpbm(&to_string(|out| write_basins_ppm(&map, out)));
+
+            assert_eq!(header, vec!["P3", "10", "5", "255"]);
+            assert_eq!(samples.len(), 10 * 5 * 3);
+
+            let pixel = |x: usize, y: usize| &samples[(y * 10 + x) * 3..(y * 10 + x) * 3 + 3];
+            assert_eq!(pixel(2, 0), [0, 0, 0]);
+            assert_eq!(pixel(1, 0), [255, 255, 255]);
+            assert_eq!(pixel(0, 0), basin_colour(0));
+            assert_eq!(pixel(0, 1), basin_colour(0));
+            assert_eq!(pixel(9, 4), basin_colour(3));
+        }
+
+        #[test]
+        fn basin_colour_test() {
+            for label in 0..1000 {
+                let colour = basin_colour(label);
+                assert_ne!(colour, [0, 0, 0]);
+                assert_ne!(colour, [255, 255, 255]);
+            }
+            assert_ne!(basin_colour(0), basin_colour(1));
+        }
+
+        #[test]
+        fn line_length_test() {
+            let input = parse_input();
+            let map = basin_map(&input);
+
+            let heightmap = to_string(|out| write_heightmap_pgm(&input, out));
+            let basins = to_string(|out| write_basins_ppm(&map, out));
+
+            for image in [&heightmap, &basins] {
+                assert!(image.lines().all(|line| line.len() <= 70));
+            }
+            assert_eq!(parse_netpbm(&heightmap).1.len(), 100 * 100);
+            assert_eq!(parse_netpbm(&basins).1.len(), 100 * 100 * 3);
+        }
+    }
+
+    mod rain_fill {
+        use super::{super::rain_fill, parse_input, parse_input_str, EXAMPLE};
+
+        #[test]
+        fn example_test() {
+            let fill = rain_fill(&parse_input_str(EXAMPLE));
+
+            assert_eq!(fill.volume, 11);
+            assert_eq!(
+                fill.depths,
+                vec![
+                    vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                    vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+                    vec![0, 0, 3, 2, 1, 0, 0, 0, 0, 0],
+                    vec![0, 1, 2, 1, 0, 0, 0, 0, 0, 0],
+                    vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                ]
+            );
+        }
+
+        #[test]
+        fn walls_test() {
+            // Water spills over the lowest point of the wall.
+            let fill = rain_fill(&parse_input_str("99999\n91029\n99969"));
+            assert_eq!(fill.volume, 5 + 6 + 4);
+
+            // Nothing is held without walls.
+            assert_eq!(rain_fill(&parse_input_str("012\n345")).volume, 0);
+            assert_eq!(rain_fill(&[]).volume, 0);
+        }
+
+        #[test]
+        fn solution() {
+            assert_eq!(rain_fill(&parse_input()).volume, 20177);
+        }
+    }
+
+    mod merge_tree {
+        use super::{
+            super::{basin_map, merge_tree},
+            parse_input, parse_input_str, EXAMPLE,
+        };
+
+        #[test]
+        fn merges_test() {
+            let tree = merge_tree(&parse_input_str("10201"));
+            assert_eq!(tree.nodes.len(), 3);
+            assert_eq!(tree.roots(), vec![2]);
+
+            let root = &tree.nodes[2];
+            assert_eq!(
+                (root.level, root.cell, root.size, &root.children),
+                (2, (2, 0), 5, &vec![0, 1])
+            );
+            assert_eq!(tree.nodes[0].cell, (1, 0));
+            assert_eq!(tree.nodes[1].cell, (3, 0));
+            assert_eq!(tree.nodes[0].parent, Some(2));
+
+            // Nothing joins within a slope.
+            let tree = merge_tree(&parse_input_str("21012"));
+            assert_eq!(tree.nodes.len(), 1);
+            assert_eq!(tree.nodes[0].cell, (2, 0));
+        }
+
+        #[test]
+        fn plateau_test() {
+            // The flat saddle joins the two regions rather than starting a
+            // third.
+            let tree = merge_tree(&parse_input_str("1022201"));
+            assert_eq!(tree.nodes.len(), 3);
+
+            let root = &tree.nodes[2];
+            assert_eq!((root.level, root.cell, root.size), (2, (3, 0), 7));
+            assert_eq!(tree.connection((0, 0), (6, 0)), Some(root));
+            assert_eq!(tree.connection((0, 0), (2, 0)), Some(&tree.nodes[0]));
+        }
+
+        #[test]
+        fn disconnected_test() {
+            let tree = merge_tree(&parse_input_str("0\n\n1"));
+            assert_eq!(tree.roots(), vec![0, 1]);
+            assert_eq!(tree.connection((0, 0), (0, 2)), None);
+        }
+
+        #[test]
+        fn example_test() {
+            let input = parse_input_str(EXAMPLE);
+            let tree = merge_tree(&input);
+
+            let leaves = tree
+                .nodes
+                .iter()
+                .filter(|node| node.children.is_empty())
+                .map(|node| (node.level, node.cell))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                leaves,
+                vec![(0, (9, 0)), (1, (1, 0)), (5, (2, 2)), (5, (6, 4))]
+            );
+            assert_eq!(tree.roots().len(), 1);
+
+            // The basins of sizes 14 and 9 meet once the 9 between them is
+            // under.
+            let connection = tree.connection((2, 2), (9, 0)).unwrap();
+            assert_eq!(
+                (connection.level, connection.cell, connection.size),
+                (9, (4, 0), 29)
+            );
+        }
+
+        #[test]
+        fn solution() {
+            let input = parse_input();
+            let tree = merge_tree(&input);
+
+            let mut basins = basin_map(&input).basins;
+            basins.sort_by_key(|basin| std::cmp::Reverse(basin.size));
+            let connection = tree
+                .connection(basins[0].low_point, basins[1].low_point)
+                .unwrap();
+            assert_eq!(connection.level, 9);
+            assert_eq!(tree.roots().len(), 1);
+        }
+    }
+
+    mod streaming {
+        use crate::common::read_input;
+
+        use super::{
+            super::{
+                streaming_basin_sizes, streaming_low_points, streaming_risk_level_sum,
+                streaming_three_largest_product,
+            },
+            parse_input_str, EXAMPLE,
+        };
+
+        fn parse_row(line: String) -> Vec<i8> {
+            line.chars()
+                .map(|char| char.to_digit(10).unwrap() as i8)
+                .collect()
+        }
+
+        fn basin_sizes(input: &str) -> Vec<u64> {
+            let mut sizes = Vec::new();
+            streaming_basin_sizes(parse_input_str(input), |size| sizes.push(size));
+            sizes
+        }
+
+        #[test]
+        fn low_points_test() {
+            let mut low_points = Vec::new();
+            streaming_low_points(parse_input_str(EXAMPLE), |cell, height| {
+                low_points.push((cell, height))
+            });
+
+            assert_eq!(
+                low_points,
+                vec![((1, 0), 1), ((9, 0), 0), ((2, 2), 5), ((6, 4), 5)]
+            );
+            assert_eq!(streaming_risk_level_sum(parse_input_str(EXAMPLE)), 15);
+            assert_eq!(streaming_risk_level_sum(Vec::new()), 0);
+        }
+
+        #[test]
+        fn basin_sizes_test() {
+            let mut sizes = basin_sizes(EXAMPLE);
+            sizes.sort_unstable();
+            assert_eq!(sizes, vec![3, 9, 9, 14]);
+            assert_eq!(
+                streaming_three_largest_product(parse_input_str(EXAMPLE)),
+                1134
+            );
+
+            // Two labels in the first row turn out to be the same basin.
+            assert_eq!(basin_sizes("0990\n0000"), vec![6]);
+            // Finished basins are reported as soon as they are.
+            assert_eq!(basin_sizes("19\n99\n91"), vec![1, 1]);
+            assert_eq!(basin_sizes("090\n090\n000\n999\n000"), vec![7, 3]);
+        }
+
+        #[test]
+        fn solution() {
+            let rows = || read_input("day_09").map(parse_row);
+
+            assert_eq!(streaming_risk_level_sum(rows()), 468);
+            assert_eq!(streaming_three_largest_product(rows()), 1280496);
+        }
+    }
 }
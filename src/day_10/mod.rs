@@ -1,128 +1,816 @@
-use std::collections::HashSet;
+/// What is wrong with a navigation line, if anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LineStatus {
+    Valid,
+    /// `found`, starting at the 0-based `column`, doesn't close the last open
+    /// chunk. `expected` is the delimiter that would have, and `opener` the
+    /// column and delimiter that opened the chunk, or both are `None` if no
+    /// chunk was open.
+    Corrupted {
+        column: usize,
+        expected: Option<String>,
+        found: String,
+        opener: Option<(usize, String)>,
+    },
+    /// The closing delimiters that complete the line, innermost chunk first.
+    Incomplete {
+        missing_closers: Vec<String>,
+    },
+}
 
-#[allow(dead_code)]
-fn syntax_error_score(input: Vec<String>) -> usize {
-    let (score, _) = find_corrupted(&input);
-    score
+/// Delimiters of a chunk, and what they score when the checker finds them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BracketPair {
+    open: String,
+    close: String,
+    /// Points for a corrupted line that has `close` where another delimiter
+    /// was expected.
+    corruption_points: usize,
+    /// Points for `close` when completing an incomplete line.
+    completion_points: usize,
+}
+
+/// The delimiters and other characters a line may contain.
+#[derive(Debug, Clone)]
+struct BracketGrammar {
+    pairs: Vec<BracketPair>,
+    /// Characters outside delimiters that are skipped. Any other character
+    /// corrupts the line.
+    ignored: fn(char) -> bool,
+    /// Characters that start and end a region where nothing is checked.
+    quotes: Vec<char>,
 }
 
-fn find_corrupted(input: &Vec<String>) -> (usize, HashSet<usize>) {
-    let mut score = 0;
-    let mut corrupted = HashSet::new();
+/// A delimiter, or a character that is neither a delimiter nor ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Token<'a> {
+    text: &'a str,
+    offset: usize,
+    column: usize,
+    kind: TokenKind,
+}
 
-    for (i, line) in input.into_iter().enumerate() {
-        let mut stack = Vec::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    /// Opens the pair at this index.
+    Open(usize),
+    /// Closes the pair at this index.
+    Close(usize),
+    Unexpected,
+}
 
-        for current in line.chars() {
-            if stack.is_empty() {
-                stack.push(current);
-                continue;
-            }
+impl BracketGrammar {
+    /// A grammar of `(open, close, corruption_points, completion_points)`
+    /// pairs, with nothing ignored or quoted.
+    ///
+    /// Delimiters can be several characters long. When several match, the
+    /// longest wins, and an opening delimiter wins over a closing one. A
+    /// delimiter that starts or ends with a letter or digit only matches as a
+    /// whole word, so `end` is not found in `append`.
+    fn new(pairs: &[(&str, &str, usize, usize)]) -> Self {
+        let pairs = pairs
+            .iter()
+            .map(
+                |(open, close, corruption_points, completion_points)| BracketPair {
+                    open: open.to_string(),
+                    close: close.to_string(),
+                    corruption_points: *corruption_points,
+                    completion_points: *completion_points,
+                },
+            )
+            .collect::<Vec<_>>();
+        assert!(
+            pairs
+                .iter()
+                .all(|pair| !pair.open.is_empty() && !pair.close.is_empty()),
+            "Delimiters can't be empty"
+        );
+
+        Self {
+            pairs,
+            ignored: |_| false,
+            quotes: Vec::new(),
+        }
+    }
+
+    /// The four chunk types of the navigation subsystem.
+    fn navigation_subsystem() -> Self {
+        Self::new(&[
+            ("(", ")", 3, 1),
+            ("[", "]", 57, 2),
+            ("{", "}", 1197, 3),
+            ("<", ">", 25137, 4),
+        ])
+    }
+
+    #[allow(dead_code)]
+    fn with_ignored(mut self, ignored: fn(char) -> bool) -> Self {
+        self.ignored = ignored;
+        self
+    }
 
-            if is_open(current) {
-                stack.push(current);
-                continue;
+    /// Quoted regions end at the next occurrence of the same quote, and can't
+    /// be escaped.
+    #[allow(dead_code)]
+    fn with_quotes(mut self, quotes: &str) -> Self {
+        self.quotes = quotes.chars().collect();
+        self
+    }
+
+    /// Checks a line in one pass over a stack of open chunks.
+    ///
+    /// A quote left open makes the line incomplete, with the quote as the
+    /// first missing closer.
+    fn classify(&self, line: &str) -> LineStatus {
+        let (tokens, quote) = self.tokenize(line);
+        let mut stack: Vec<(&BracketPair, usize)> = Vec::new();
+
+        for token in tokens {
+            match token.kind {
+                TokenKind::Open(pair) => stack.push((&self.pairs[pair], token.column)),
+                TokenKind::Close(pair)
+                    if stack
+                        .last()
+                        .is_some_and(|(open, _)| open.close == self.pairs[pair].close) =>
+                {
+                    stack.pop();
+                }
+                _ => {
+                    let open = stack.last();
+                    return LineStatus::Corrupted {
+                        column: token.column,
+                        expected: open.map(|(open, _)| open.close.clone()),
+                        found: token.text.to_string(),
+                        opener: open.map(|(open, column)| (*column, open.open.clone())),
+                    };
+                }
             }
+        }
+
+        let missing_closers = quote
+            .map(|quote| quote.to_string())
+            .into_iter()
+            .chain(stack.into_iter().rev().map(|(open, _)| open.close.clone()))
+            .collect::<Vec<_>>();
+
+        if missing_closers.is_empty() {
+            LineStatus::Valid
+        } else {
+            LineStatus::Incomplete { missing_closers }
+        }
+    }
 
-            let prev = stack.last().unwrap();
-            if to_closing(*prev) == current {
-                stack.pop();
-                continue;
+    /// Splits a line into delimiters and unexpected characters, skipping
+    /// ignored characters and quoted regions. Also returns the quote left open
+    /// at the end, if any.
+    fn tokenize<'a>(&self, line: &'a str) -> (Vec<Token<'a>>, Option<char>) {
+        let mut tokens = Vec::new();
+        let mut quote = None;
+        let mut offset = 0;
+        let mut column = 0;
+
+        while let Some(current) = line[offset..].chars().next() {
+            let (length, kind) = if let Some(open_quote) = quote {
+                if current == open_quote {
+                    quote = None;
+                }
+                (current.len_utf8(), None)
+            } else if self.quotes.contains(&current) {
+                quote = Some(current);
+                (current.len_utf8(), None)
+            } else if let Some(pair) = self.delimiter_at(line, offset, |pair| &pair.open) {
+                (self.pairs[pair].open.len(), Some(TokenKind::Open(pair)))
+            } else if let Some(pair) = self.delimiter_at(line, offset, |pair| &pair.close) {
+                (self.pairs[pair].close.len(), Some(TokenKind::Close(pair)))
+            } else if (self.ignored)(current) {
+                (current.len_utf8(), None)
+            } else {
+                (current.len_utf8(), Some(TokenKind::Unexpected))
+            };
+
+            let text = &line[offset..offset + length];
+            if let Some(kind) = kind {
+                tokens.push(Token {
+                    text,
+                    offset,
+                    column,
+                    kind,
+                });
             }
 
-            score += to_corrupted_points(current);
-            corrupted.insert(i);
-            break;
+            column += text.chars().count();
+            offset += length;
         }
+
+        (tokens, quote)
     }
 
-    (score, corrupted)
-}
+    /// Index of the pair with the longest delimiter, as chosen by `delimiter`,
+    /// that starts at `offset`.
+    fn delimiter_at(
+        &self,
+        line: &str,
+        offset: usize,
+        delimiter: fn(&BracketPair) -> &String,
+    ) -> Option<usize> {
+        (0..self.pairs.len())
+            .filter(|pair| {
+                let delimiter = delimiter(&self.pairs[*pair]);
+                if !line[offset..].starts_with(delimiter.as_str()) {
+                    return false;
+                }
 
-fn to_corrupted_points(x: char) -> usize {
-    match x {
-        ')' => 3,
-        ']' => 57,
-        '}' => 1197,
-        '>' => 25137,
-        _ => panic!("Unknown char {}", x),
+                let before = line[..offset].chars().next_back();
+                let after = line[offset + delimiter.len()..].chars().next();
+                !(is_word(delimiter.chars().next()) && is_word(before)
+                    || is_word(delimiter.chars().next_back()) && is_word(after))
+            })
+            .max_by_key(|pair| delimiter(&self.pairs[*pair]).len())
     }
-}
 
-fn to_closing(x: char) -> char {
-    match x {
-        '(' => ')',
-        '[' => ']',
-        '{' => '}',
-        '<' => '>',
-        _ => panic!("Unknown char {}", x),
+    /// Points for a corrupted line that has `found` instead of the expected
+    /// delimiter. Characters that aren't closing delimiters score nothing.
+    fn corruption_points(&self, found: &str) -> usize {
+        self.pairs
+            .iter()
+            .find(|pair| pair.close == found)
+            .map_or(0, |pair| pair.corruption_points)
     }
-}
 
-fn is_open(x: char) -> bool {
-    match x {
-        '(' | '[' | '{' | '<' => true,
-        _ => false,
+    /// Score of the closers that complete an incomplete line. Quotes score
+    /// nothing.
+    fn completion_score(&self, missing_closers: &[String]) -> usize {
+        missing_closers.iter().fold(0, |acc, closer| {
+            acc * 5
+                + self
+                    .pairs
+                    .iter()
+                    .find(|pair| pair.close == *closer)
+                    .map_or(0, |pair| pair.completion_points)
+        })
     }
 }
 
+/// Checks a navigation subsystem line.
 #[allow(dead_code)]
-fn middle_incomplete(input: Vec<String>) -> usize {
-    let (_, corrupted_lines_indices) = find_corrupted(&input);
+fn classify_line(line: &str) -> LineStatus {
+    BracketGrammar::navigation_subsystem().classify(line)
+}
 
-    let incomplete_lines = input
-        .into_iter()
-        .enumerate()
-        .filter(|(i, _)| !corrupted_lines_indices.contains(i))
-        .map(|(_, line)| line);
+#[allow(dead_code)]
+fn syntax_error_score(input: Vec<String>) -> usize {
+    let grammar = BracketGrammar::navigation_subsystem();
 
-    let mut incomplete_scores = Vec::new();
+    input
+        .iter()
+        .map(|line| match grammar.classify(line) {
+            LineStatus::Corrupted { found, .. } => grammar.corruption_points(&found),
+            _ => 0,
+        })
+        .sum()
+}
 
-    for line in incomplete_lines {
-        let mut stack = Vec::new();
+#[allow(dead_code)]
+fn middle_incomplete(input: Vec<String>) -> usize {
+    let grammar = BracketGrammar::navigation_subsystem();
 
-        for current in line.chars() {
-            if stack.is_empty() {
-                stack.push(current);
-                continue;
+    let mut incomplete_scores = input
+        .iter()
+        .filter_map(|line| match grammar.classify(line) {
+            // Completing a line is repairing it with insertions only.
+            LineStatus::Incomplete { .. } => {
+                let completion = grammar
+                    .repair(line, RepairMode::InsertOnly)
+                    .expect("Incomplete lines can be completed");
+                let closers = completion
+                    .edits
+                    .into_iter()
+                    .filter_map(|edit| match edit {
+                        Edit::Insert { delimiter, .. } => Some(delimiter),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                Some(grammar.completion_score(&closers))
             }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    incomplete_scores.sort();
+
+    assert!(
+        incomplete_scores.len() % 2 == 1,
+        "Always odd number of scores to consider"
+    );
+    // Median.
+    *incomplete_scores.get(incomplete_scores.len() / 2).unwrap()
+}
+
+/// Which edits a repair may make.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RepairMode {
+    AnyEdit,
+    InsertOnly,
+}
 
-            if is_open(current) {
-                stack.push(current);
-                continue;
+/// A change to a line. Columns are 0-based and refer to the original line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    /// `delimiter` goes before the character at `column`, or at the end if
+    /// `column` is the length of the line.
+    Insert {
+        column: usize,
+        delimiter: String,
+    },
+    Delete {
+        column: usize,
+        delimiter: String,
+    },
+    Substitute {
+        column: usize,
+        found: String,
+        replacement: String,
+    },
+}
+
+/// A balanced line and the edits that made it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Repair {
+    line: String,
+    edits: Vec<Edit>,
+}
+
+/// How a span of tokens is balanced, in [`BracketGrammar::repair`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Empty,
+    Delete,
+    /// Insert an opener for the first token.
+    InsertOpener,
+    /// Pair the first token with the token at this index, substituting either
+    /// if needed.
+    Match(usize),
+    /// Insert a closer for the first token before the token at this index.
+    InsertCloser(usize),
+}
+
+/// One step of rebuilding a repaired line, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step<'a> {
+    Keep(usize),
+    Delete(usize),
+    Substitute(usize, &'a str),
+    Insert(&'a str),
+}
+
+impl BracketGrammar {
+    /// Makes a line balanced with as few edits as possible, or `None` if that
+    /// can't be done in `mode`.
+    ///
+    /// Each delimiter inserted, deleted or substituted is one edit. Between
+    /// repairs with the same number of edits, the one with the most
+    /// insertions wins, and then the one inserting closers latest, so an
+    /// incomplete line is completed at its end. Unexpected characters can be
+    /// deleted or substituted, but are never kept. A quote left open is closed
+    /// at the end of the line.
+    ///
+    /// This is an edit distance over the delimiters: the cheapest way to
+    /// balance each span of them is worked out from the shorter spans inside
+    /// it, in O(n^3) for n delimiters.
+    fn repair(&self, line: &str, mode: RepairMode) -> Option<Repair> {
+        let (tokens, quote) = self.tokenize(line);
+        let n = tokens.len();
+
+        // Edits are weighed so that fewer edits always wins, and then fewer
+        // edits that aren't insertions. Impossible is `usize::MAX`.
+        const IMPOSSIBLE: usize = usize::MAX;
+        let add = |a: usize, b: usize| a.saturating_add(b);
+        let insertion = n + 1;
+        let change = match mode {
+            RepairMode::AnyEdit => n + 2,
+            RepairMode::InsertOnly => IMPOSSIBLE,
+        };
+        let closes = |open: usize, close: usize| self.pairs[open].close == self.pairs[close].close;
+
+        // `costs[i][j]` is the cheapest way to balance `tokens[i..j]`.
+        let mut costs = vec![vec![0; n + 1]; n + 1];
+        let mut choices = vec![vec![Choice::Empty; n + 1]; n + 1];
+
+        for length in 1..=n {
+            for i in 0..=n - length {
+                let j = i + length;
+                let mut best = (IMPOSSIBLE, Choice::Empty);
+                // Later candidates win ties.
+                let mut consider = |cost: usize, choice: Choice| {
+                    if cost != IMPOSSIBLE && cost <= best.0 {
+                        best = (cost, choice);
+                    }
+                };
+
+                consider(add(change, costs[i + 1][j]), Choice::Delete);
+                if let TokenKind::Close(_) = tokens[i].kind {
+                    consider(add(insertion, costs[i + 1][j]), Choice::InsertOpener);
+                }
+
+                for k in i + 1..j {
+                    let pairing = match (tokens[i].kind, tokens[k].kind) {
+                        (TokenKind::Open(open), TokenKind::Close(close)) if closes(open, close) => {
+                            0
+                        }
+                        // Substitute one of them. Substituting both is never
+                        // better than deleting both.
+                        (TokenKind::Open(_), _) | (_, TokenKind::Close(_)) => change,
+                        _ => IMPOSSIBLE,
+                    };
+                    let inside = add(costs[i + 1][k], costs[k + 1][j]);
+                    consider(add(pairing, inside), Choice::Match(k));
+                }
+
+                if let TokenKind::Open(_) = tokens[i].kind {
+                    (i + 1..=j).for_each(|k| {
+                        let inside = add(costs[i + 1][k], costs[k][j]);
+                        consider(add(insertion, inside), Choice::InsertCloser(k));
+                    });
+                }
+
+                (costs[i][j], choices[i][j]) = best;
             }
+        }
+
+        if costs[0][n] == IMPOSSIBLE {
+            return None;
+        }
+
+        let mut steps = Vec::new();
+        self.repair_steps(&tokens, &choices, 0, n, &mut steps);
 
-            let prev = stack.last().unwrap();
-            if to_closing(*prev) == current {
-                stack.pop();
-                continue;
+        Some(self.apply_steps(line, &tokens, quote, &steps))
+    }
+
+    /// Appends the steps that balance `tokens[i..j]`, in order.
+    fn repair_steps<'a>(
+        &'a self,
+        tokens: &[Token],
+        choices: &[Vec<Choice>],
+        i: usize,
+        j: usize,
+        steps: &mut Vec<Step<'a>>,
+    ) {
+        match choices[i][j] {
+            Choice::Empty => {}
+            Choice::Delete => {
+                steps.push(Step::Delete(i));
+                self.repair_steps(tokens, choices, i + 1, j, steps);
+            }
+            Choice::InsertOpener => {
+                let TokenKind::Close(pair) = tokens[i].kind else {
+                    panic!("Token {} should be a closer", i);
+                };
+                steps.push(Step::Insert(&self.pairs[pair].open));
+                steps.push(Step::Keep(i));
+                self.repair_steps(tokens, choices, i + 1, j, steps);
             }
+            Choice::Match(k) => {
+                let (open, close) = match (tokens[i].kind, tokens[k].kind) {
+                    (TokenKind::Open(open), TokenKind::Close(close))
+                        if self.pairs[open].close == self.pairs[close].close =>
+                    {
+                        (Step::Keep(i), Step::Keep(k))
+                    }
+                    (TokenKind::Open(open), _) => {
+                        (Step::Keep(i), Step::Substitute(k, &self.pairs[open].close))
+                    }
+                    (_, TokenKind::Close(close)) => {
+                        (Step::Substitute(i, &self.pairs[close].open), Step::Keep(k))
+                    }
+                    _ => panic!("Tokens {} and {} can't be paired", i, k),
+                };
 
-            panic!("line {} should be corrupted", line);
+                steps.push(open);
+                self.repair_steps(tokens, choices, i + 1, k, steps);
+                steps.push(close);
+                self.repair_steps(tokens, choices, k + 1, j, steps);
+            }
+            Choice::InsertCloser(k) => {
+                let TokenKind::Open(pair) = tokens[i].kind else {
+                    panic!("Token {} should be an opener", i);
+                };
+                steps.push(Step::Keep(i));
+                self.repair_steps(tokens, choices, i + 1, k, steps);
+                steps.push(Step::Insert(&self.pairs[pair].close));
+                self.repair_steps(tokens, choices, k, j, steps);
+            }
         }
+    }
 
-        let score = stack.into_iter().rev().fold(0, |acc, x| {
-            acc * 5
-                + match to_closing(x) {
-                    ')' => 1,
-                    ']' => 2,
-                    '}' => 3,
-                    '>' => 4,
-                    _ => panic!("Unexpected char {}", x),
+    /// Rebuilds the line from the repair steps.
+    fn apply_steps(
+        &self,
+        line: &str,
+        tokens: &[Token],
+        quote: Option<char>,
+        steps: &[Step],
+    ) -> Repair {
+        let line_length = line.chars().count();
+        let mut result = RepairedLine::default();
+        let mut edits = Vec::new();
+        // End of the original text copied so far.
+        let mut offset = 0;
+        let mut next_token = 0;
+
+        // Copies the rest of the line and closes the quote left open, before
+        // anything is inserted at the end.
+        let finish_line = |result: &mut RepairedLine, edits: &mut Vec<Edit>, offset: &mut usize| {
+            result.push_original(&line[*offset..]);
+            *offset = line.len();
+
+            if let Some(quote) = quote {
+                let quote = quote.to_string();
+                result.push_inserted(&quote);
+                edits.push(Edit::Insert {
+                    column: line_length,
+                    delimiter: quote,
+                });
+            }
+        };
+        let mut finished = false;
+
+        for step in steps {
+            match step {
+                Step::Keep(token) | Step::Delete(token) | Step::Substitute(token, _) => {
+                    let token = &tokens[*token];
+                    result.push_original(&line[offset..token.offset]);
+                    offset = token.offset + token.text.len();
+                    next_token += 1;
+                }
+                // Insertions go right before the next token, after any text
+                // in between.
+                Step::Insert(_) => match tokens.get(next_token) {
+                    Some(token) => {
+                        result.push_original(&line[offset..token.offset]);
+                        offset = token.offset;
+                    }
+                    None if !finished => {
+                        finish_line(&mut result, &mut edits, &mut offset);
+                        finished = true;
+                    }
+                    None => {}
+                },
+            }
+
+            match step {
+                Step::Keep(token) => result.push_original(tokens[*token].text),
+                Step::Delete(token) => edits.push(Edit::Delete {
+                    column: tokens[*token].column,
+                    delimiter: tokens[*token].text.to_string(),
+                }),
+                Step::Substitute(token, replacement) => {
+                    result.push_inserted(replacement);
+                    edits.push(Edit::Substitute {
+                        column: tokens[*token].column,
+                        found: tokens[*token].text.to_string(),
+                        replacement: replacement.to_string(),
+                    });
+                }
+                Step::Insert(delimiter) => {
+                    result.push_inserted(delimiter);
+                    edits.push(Edit::Insert {
+                        column: tokens
+                            .get(next_token)
+                            .map_or(line_length, |token| token.column),
+                        delimiter: delimiter.to_string(),
+                    });
                 }
-        });
+            }
+        }
+        if !finished {
+            finish_line(&mut result, &mut edits, &mut offset);
+        }
 
-        incomplete_scores.push(score);
+        Repair {
+            line: result.line,
+            edits,
+        }
     }
+}
 
-    incomplete_scores.sort();
+/// A line being rebuilt, where inserted delimiters get a space if they would
+/// otherwise run into a word.
+#[derive(Debug, Default)]
+struct RepairedLine {
+    line: String,
+    /// Whether the last text pushed was inserted and ends with a letter or
+    /// digit.
+    after_inserted_word: bool,
+}
 
-    assert!(
-        incomplete_scores.len() % 2 == 1,
-        "Always odd number of scores to consider"
-    );
-    // Median.
-    *incomplete_scores.get(incomplete_scores.len() / 2).unwrap()
+impl RepairedLine {
+    fn push_original(&mut self, text: &str) {
+        if self.after_inserted_word && is_word(text.chars().next()) {
+            self.line.push(' ');
+        }
+        if !text.is_empty() {
+            self.after_inserted_word = false;
+        }
+        self.line.push_str(text);
+    }
+
+    fn push_inserted(&mut self, text: &str) {
+        if is_word(self.line.chars().next_back()) && is_word(text.chars().next()) {
+            self.line.push(' ');
+        }
+        self.line.push_str(text);
+        self.after_inserted_word = is_word(text.chars().next_back());
+    }
+}
+
+/// Whether `char` is a letter or digit, which a delimiter can't run into.
+fn is_word(char: Option<char>) -> bool {
+    char.is_some_and(|char| char.is_alphanumeric())
+}
+
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+/// Wraps `text` in an ANSI `style` if `colour` is on.
+fn paint(text: &str, style: &str, colour: bool) -> String {
+    if colour {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Appends closing delimiters to a line, with a space between two that would
+/// otherwise run together into one word, like `end end`.
+fn append_delimiters(line: &str, delimiters: &[String]) -> String {
+    let mut result = line.to_string();
+
+    for delimiter in delimiters {
+        if is_word(result.chars().next_back()) && is_word(delimiter.chars().next()) {
+            result.push(' ');
+        }
+        result.push_str(delimiter);
+    }
+    result
+}
+
+/// A rustc style report of every line that isn't valid, with a caret under
+/// what is wrong. Line numbers and columns start at 1.
+#[allow(dead_code)]
+fn report(grammar: &BracketGrammar, input: &[String], colour: bool) -> String {
+    input
+        .iter()
+        .enumerate()
+        .filter_map(|(i, line)| diagnose_line(grammar, i + 1, line, colour))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The report of a single line, or `None` if it is valid.
+fn diagnose_line(
+    grammar: &BracketGrammar,
+    line_number: usize,
+    line: &str,
+    colour: bool,
+) -> Option<String> {
+    let code = |text: &str| format!("`{}`", text);
+
+    // Heading, column of the carets, the carets and their label, the opener to
+    // point at and the help note.
+    let (heading, column, carets, label, opener, help) = match grammar.classify(line) {
+        LineStatus::Valid => return None,
+        LineStatus::Corrupted {
+            column,
+            expected,
+            found,
+            opener,
+        } => {
+            let (heading, label) = match &expected {
+                Some(expected) => (
+                    format!(
+                        "{}: expected {}, found {}",
+                        paint("error", RED, colour),
+                        code(expected),
+                        code(&found)
+                    ),
+                    format!("expected {}", code(expected)),
+                ),
+                None => (
+                    format!(
+                        "{}: unexpected {}",
+                        paint("error", RED, colour),
+                        code(&found)
+                    ),
+                    "no chunk is open".to_string(),
+                ),
+            };
+            let caret = paint(&"^".repeat(found.chars().count()), RED, colour);
+            (
+                heading,
+                column,
+                caret,
+                paint(&label, RED, colour),
+                opener,
+                None,
+            )
+        }
+        LineStatus::Incomplete { missing_closers } => {
+            let missing = append_delimiters("", &missing_closers);
+            let heading = format!(
+                "{}: incomplete line, missing {}",
+                paint("warning", YELLOW, colour),
+                code(&missing)
+            );
+            let label = paint(&format!("add {} here", code(&missing)), YELLOW, colour);
+            let help = format!(
+                "complete it as {}",
+                code(&append_delimiters(line, &missing_closers))
+            );
+            let column = line.chars().count();
+            (
+                heading,
+                column,
+                paint("^", YELLOW, colour),
+                label,
+                None,
+                Some(help),
+            )
+        }
+    };
+
+    let number = line_number.to_string();
+    let gutter = " ".repeat(number.len());
+    let bar = paint("|", BLUE, colour);
+
+    let mut lines = vec![
+        paint(&heading, BOLD, colour),
+        format!(
+            "{}{} line {}, column {}",
+            gutter,
+            paint("-->", BLUE, colour),
+            line_number,
+            column + 1
+        ),
+        format!("{} {}", gutter, bar),
+        format!("{} {} {}", paint(&number, BLUE, colour), bar, line),
+    ];
+
+    match opener {
+        None => lines.push(format!(
+            "{} {} {}{} {}",
+            gutter,
+            bar,
+            " ".repeat(column),
+            carets,
+            label
+        )),
+        Some((opener_column, open)) => {
+            let opener_width = open.chars().count();
+            lines.push(format!(
+                "{} {} {}{}{}{} {}",
+                gutter,
+                bar,
+                " ".repeat(opener_column),
+                paint(&"-".repeat(opener_width), BLUE, colour),
+                " ".repeat(column - opener_column - opener_width),
+                carets,
+                label
+            ));
+            let indent = " ".repeat(opener_column);
+            lines.push(format!(
+                "{} {} {}{}",
+                gutter,
+                bar,
+                indent,
+                paint("|", BLUE, colour)
+            ));
+            lines.push(format!(
+                "{} {} {}{}",
+                gutter,
+                bar,
+                indent,
+                paint(&format!("{} opened here", code(&open)), BLUE, colour)
+            ));
+        }
+    }
+
+    if let Some(help) = help {
+        lines.push(format!("{} {}", gutter, bar));
+        lines.push(format!(
+            "{} {} help: {}",
+            gutter,
+            paint("=", BLUE, colour),
+            help
+        ));
+    }
+
+    Some(lines.join("\n") + "\n")
 }
 
 #[cfg(test)]
@@ -183,4 +871,441 @@ mod tests {
             assert_eq!(middle_incomplete(input), 1105996483);
         }
     }
+
+    mod classify_line {
+        use super::{
+            super::{classify_line, LineStatus},
+            parse_input, EXAMPLE,
+        };
+
+        fn to_strings(closers: &str) -> Vec<String> {
+            closers.chars().map(|closer| closer.to_string()).collect()
+        }
+
+        #[test]
+        fn example_test() {
+            let statuses = EXAMPLE.lines().map(classify_line).collect::<Vec<_>>();
+
+            assert_eq!(
+                statuses[0],
+                LineStatus::Incomplete {
+                    missing_closers: to_strings("}}]])})]")
+                }
+            );
+            assert_eq!(
+                statuses[2],
+                LineStatus::Corrupted {
+                    column: 12,
+                    expected: Some("]".to_string()),
+                    found: "}".to_string(),
+                    opener: Some((7, "[".to_string()))
+                }
+            );
+            assert_eq!(
+                statuses
+                    .iter()
+                    .filter(|status| matches!(status, LineStatus::Corrupted { .. }))
+                    .count(),
+                5
+            );
+        }
+
+        #[test]
+        fn edge_cases_test() {
+            assert_eq!(classify_line(""), LineStatus::Valid);
+            assert_eq!(classify_line("([]){<>}"), LineStatus::Valid);
+            assert_eq!(
+                classify_line("()>"),
+                LineStatus::Corrupted {
+                    column: 2,
+                    expected: None,
+                    found: ">".to_string(),
+                    opener: None
+                }
+            );
+            assert_eq!(
+                classify_line("<(["),
+                LineStatus::Incomplete {
+                    missing_closers: to_strings("])>")
+                }
+            );
+        }
+
+        #[test]
+        fn solution() {
+            assert!(parse_input().all(|line| classify_line(&line) != LineStatus::Valid));
+        }
+    }
+
+    mod bracket_grammar {
+        use super::super::{BracketGrammar, LineStatus};
+
+        fn strings(strings: &[&str]) -> Vec<String> {
+            strings.iter().map(|string| string.to_string()).collect()
+        }
+
+        fn corrupted(
+            column: usize,
+            expected: Option<&str>,
+            found: &str,
+            opener: Option<(usize, &str)>,
+        ) -> LineStatus {
+            LineStatus::Corrupted {
+                column,
+                expected: expected.map(str::to_string),
+                found: found.to_string(),
+                opener: opener.map(|(column, open)| (column, open.to_string())),
+            }
+        }
+
+        #[test]
+        fn ignored_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+            assert_eq!(
+                grammar.classify("(a)"),
+                corrupted(1, Some(")"), "a", Some((0, "(")))
+            );
+            assert_eq!(grammar.corruption_points("a"), 0);
+
+            let grammar = grammar.with_ignored(|char| char.is_whitespace());
+            assert_eq!(grammar.classify("( [ ] )"), LineStatus::Valid);
+            assert_eq!(
+                grammar.classify("( [ ) ]"),
+                corrupted(4, Some("]"), ")", Some((2, "[")))
+            );
+        }
+
+        #[test]
+        fn quotes_test() {
+            let grammar = BracketGrammar::navigation_subsystem()
+                .with_ignored(|char| char.is_alphanumeric() || " :,".contains(char))
+                .with_quotes("\"'");
+
+            assert_eq!(
+                grammar.classify("{ key: \"value with ) and {\" }"),
+                LineStatus::Valid
+            );
+            assert_eq!(grammar.classify("['\"]', \"'\"]"), LineStatus::Valid);
+            assert_eq!(
+                grammar.classify("{ key: \"open"),
+                LineStatus::Incomplete {
+                    missing_closers: strings(&["\"", "}"])
+                }
+            );
+            assert_eq!(grammar.completion_score(&strings(&["\"", "}"])), 3);
+        }
+
+        #[test]
+        fn multi_character_test() {
+            let grammar = BracketGrammar::new(&[("begin", "end", 10, 1), ("(*", "*)", 20, 2)])
+                .with_ignored(|char| char.is_alphanumeric() || char.is_whitespace() || char == ';');
+
+            assert_eq!(
+                grammar.classify("begin append(*ending*); friend end"),
+                LineStatus::Valid
+            );
+            assert_eq!(
+                grammar.classify("begin begin x (* end"),
+                corrupted(17, Some("*)"), "end", Some((14, "(*")))
+            );
+            assert_eq!(grammar.corruption_points("end"), 10);
+
+            let status = grammar.classify("begin (* begin");
+            assert_eq!(
+                status,
+                LineStatus::Incomplete {
+                    missing_closers: strings(&["end", "*)", "end"])
+                }
+            );
+            assert_eq!(
+                grammar.completion_score(&strings(&["end", "*)", "end"])),
+                (5 + 2) * 5 + 1
+            );
+        }
+
+        #[test]
+        fn longest_delimiter_test() {
+            let grammar = BracketGrammar::new(&[("<", ">", 1, 1), ("<<", ">>", 2, 2)]);
+
+            assert_eq!(grammar.classify("<<>><>"), LineStatus::Valid);
+            // Closers are matched greedily too.
+            assert_eq!(
+                grammar.classify("<<<>>>"),
+                corrupted(3, Some(">"), ">>", Some((2, "<")))
+            );
+            assert_eq!(
+                grammar.classify("<<>"),
+                corrupted(2, Some(">>"), ">", Some((0, "<<")))
+            );
+        }
+
+        #[test]
+        fn unicode_columns_test() {
+            let grammar = BracketGrammar::new(&[("«", "»", 1, 1)]).with_ignored(|_| true);
+            assert_eq!(grammar.classify("«é»»"), corrupted(3, None, "»", None));
+        }
+    }
+
+    mod report {
+        use super::{
+            super::{append_delimiters, report, BracketGrammar},
+            parse_input, EXAMPLE,
+        };
+
+        fn lines(input: &str) -> Vec<String> {
+            input.lines().map(|line| line.to_string()).collect()
+        }
+
+        /// Removes ANSI escape codes.
+        fn strip_colour(text: &str) -> String {
+            let mut result = String::new();
+            let mut chars = text.chars();
+            while let Some(char) = chars.next() {
+                if char == '\x1b' {
+                    chars.by_ref().find(|char| *char == 'm');
+                } else {
+                    result.push(char);
+                }
+            }
+            result
+        }
+
+        #[test]
+        fn corrupted_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+            let input = lines(EXAMPLE);
+
+            assert_eq!(
+                report(&grammar, &input[2..3], false),
+                "error: expected `]`, found `}`
+ --> line 1, column 13
+  |
+1 | {([(<{}[<>[]}>{[]{[(<()>
+  |        -    ^ expected `]`
+  |        |
+  |        `[` opened here
+"
+            );
+            assert_eq!(
+                report(&grammar, &lines("()\n()>"), false),
+                "error: unexpected `>`
+ --> line 2, column 3
+  |
+2 | ()>
+  |   ^ no chunk is open
+"
+            );
+        }
+
+        #[test]
+        fn incomplete_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+
+            assert_eq!(
+                report(&grammar, &lines("[({(<(())[]>[[{[]{<()<>>"), false),
+                "warning: incomplete line, missing `}}]])})]`
+ --> line 1, column 25
+  |
+1 | [({(<(())[]>[[{[]{<()<>>
+  |                         ^ add `}}]])})]` here
+  |
+  = help: complete it as `[({(<(())[]>[[{[]{<()<>>}}]])})]`
+"
+            );
+        }
+
+        #[test]
+        fn multi_character_test() {
+            let grammar = BracketGrammar::new(&[("begin", "end", 0, 0), ("(*", "*)", 0, 0)])
+                .with_ignored(|char| char.is_alphanumeric() || char.is_whitespace());
+
+            assert_eq!(
+                report(&grammar, &lines("begin x (* end"), false),
+                "error: expected `*)`, found `end`
+ --> line 1, column 12
+  |
+1 | begin x (* end
+  |         -- ^^^ expected `*)`
+  |         |
+  |         `(*` opened here
+"
+            );
+            assert_eq!(
+                append_delimiters("begin begin", &["end".to_string(), "end".to_string()]),
+                "begin begin end end"
+            );
+            assert_eq!(
+                append_delimiters("begin (*", &["*)".to_string(), "end".to_string()]),
+                "begin (**)end"
+            );
+        }
+
+        #[test]
+        fn colour_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+            let input = lines(EXAMPLE);
+
+            let coloured = report(&grammar, &input, true);
+            assert!(coloured.contains("\x1b[1;31merror\x1b[0m"));
+            assert_eq!(strip_colour(&coloured), report(&grammar, &input, false));
+        }
+
+        #[test]
+        fn example_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+            let report = report(&grammar, &lines(EXAMPLE), false);
+
+            assert_eq!(report.matches("error:").count(), 5);
+            assert_eq!(report.matches("warning:").count(), 5);
+            assert_eq!(report.matches(" --> line ").count(), 10);
+        }
+
+        #[test]
+        fn solution() {
+            let grammar = BracketGrammar::navigation_subsystem();
+            let input = parse_input().collect::<Vec<String>>();
+            let report = report(&grammar, &input, false);
+
+            assert_eq!(
+                report.matches("error:").count() + report.matches("warning:").count(),
+                input.len()
+            );
+        }
+    }
+
+    mod repair {
+        use super::{
+            super::{BracketGrammar, Edit, LineStatus, RepairMode},
+            parse_input, EXAMPLE,
+        };
+
+        fn insert(column: usize, delimiter: &str) -> Edit {
+            Edit::Insert {
+                column,
+                delimiter: delimiter.to_string(),
+            }
+        }
+
+        fn delete(column: usize, delimiter: &str) -> Edit {
+            Edit::Delete {
+                column,
+                delimiter: delimiter.to_string(),
+            }
+        }
+
+        fn substitute(column: usize, found: &str, replacement: &str) -> Edit {
+            Edit::Substitute {
+                column,
+                found: found.to_string(),
+                replacement: replacement.to_string(),
+            }
+        }
+
+        #[test]
+        fn corrupted_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+
+            let repair = grammar.repair("(]", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.line, "()");
+            assert_eq!(repair.edits, vec![substitute(1, "]", ")")]);
+
+            // Deleting the stray closer beats substituting both ends.
+            let repair = grammar.repair("(<)>", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.edits.len(), 2);
+
+            let repair = grammar.repair("[(])", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.edits.len(), 2);
+            assert_eq!(grammar.classify(&repair.line), LineStatus::Valid);
+
+            // An unexpected character has to go.
+            let repair = grammar.repair("(x)", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.line, "()");
+            assert_eq!(repair.edits, vec![delete(1, "x")]);
+            assert_eq!(grammar.repair("(x)", RepairMode::InsertOnly), None);
+        }
+
+        #[test]
+        fn insert_only_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+
+            let repair = grammar.repair("(]", RepairMode::InsertOnly).unwrap();
+            assert_eq!(repair.line, "([])");
+            assert_eq!(repair.edits, vec![insert(1, "["), insert(2, ")")]);
+
+            // Completion closes everything at the end.
+            let repair = grammar.repair("<{([", RepairMode::InsertOnly).unwrap();
+            assert_eq!(repair.line, "<{([])}>");
+            assert_eq!(
+                repair.edits,
+                vec![
+                    insert(4, "]"),
+                    insert(4, ")"),
+                    insert(4, "}"),
+                    insert(4, ">")
+                ]
+            );
+
+            let repair = grammar.repair("([]{}<>)", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.line, "([]{}<>)");
+            assert!(repair.edits.is_empty());
+            assert_eq!(grammar.repair("", RepairMode::InsertOnly).unwrap().line, "");
+        }
+
+        #[test]
+        fn completion_test() {
+            let grammar = BracketGrammar::navigation_subsystem();
+            let input = EXAMPLE
+                .lines()
+                .map(|line| line.to_string())
+                .chain(parse_input());
+
+            for line in input {
+                let repair = grammar.repair(&line, RepairMode::AnyEdit).unwrap();
+                assert_eq!(grammar.classify(&repair.line), LineStatus::Valid);
+
+                match grammar.classify(&line) {
+                    LineStatus::Incomplete { missing_closers } => {
+                        let completion = grammar.repair(&line, RepairMode::InsertOnly).unwrap();
+                        assert_eq!(completion.line, line.clone() + &missing_closers.concat());
+                        // Substituting can take fewer edits than completing.
+                        assert!(repair.edits.len() <= completion.edits.len());
+                    }
+                    status => assert!(
+                        matches!(status, LineStatus::Corrupted { .. }),
+                        "{:?}",
+                        status
+                    ),
+                }
+            }
+        }
+
+        #[test]
+        fn grammar_test() {
+            let grammar = BracketGrammar::new(&[("begin", "end", 0, 0), ("(", ")", 0, 0)])
+                .with_ignored(|char| char.is_alphanumeric() || char.is_whitespace())
+                .with_quotes("\"");
+
+            let repair = grammar.repair("begin (x", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.line, "begin end x");
+            assert_eq!(repair.edits, vec![substitute(6, "(", "end")]);
+
+            let repair = grammar.repair("begin (x", RepairMode::InsertOnly).unwrap();
+            assert_eq!(repair.line, "begin (x)end");
+            assert_eq!(repair.edits, vec![insert(8, ")"), insert(8, "end")]);
+
+            let repair = grammar
+                .repair("begin f(\"end)\" end", RepairMode::AnyEdit)
+                .unwrap();
+            assert_eq!(repair.line, "begin f(\"end)\" )end");
+
+            let repair = grammar.repair("begin \"x", RepairMode::InsertOnly).unwrap();
+            assert_eq!(repair.line, "begin \"x\"end");
+            assert_eq!(repair.edits, vec![insert(8, "\""), insert(8, "end")]);
+
+            // Inserting wins over deleting.
+            let repair = grammar.repair("begin ) end", RepairMode::AnyEdit).unwrap();
+            assert_eq!(repair.line, "begin () end");
+            assert_eq!(repair.edits, vec![insert(6, "(")]);
+        }
+    }
 }
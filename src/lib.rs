@@ -1,6 +1,7 @@
-#[cfg(test)]
 mod common;
 
+pub use common::intcode;
+
 mod day_01;
 mod day_02;
 mod day_03;